@@ -0,0 +1,25 @@
+use aoc_2023_rs::day17::{heat_loss_astar, route, Crucible};
+use std::time::{Duration, Instant};
+
+fn bench(name: &str, runs: u32, mut f: impl FnMut() -> usize) {
+    let mut total = Duration::ZERO;
+    let mut result = 0;
+    for _ in 0..runs {
+        let start = Instant::now();
+        result = f();
+        total += start.elapsed();
+    }
+    println!("{name:<24} {result:>6} {:>10.2?}/run", total / runs);
+}
+
+fn main() {
+    let filename = "inputs/input-17.txt";
+    for (label, crucible) in [("normal", Crucible::normal()), ("ultra", Crucible::ultra())] {
+        bench(&format!("{label} astar"), 10, || {
+            heat_loss_astar(filename, crucible).unwrap()
+        });
+        bench(&format!("{label} dial"), 10, || {
+            route(filename, crucible).unwrap().heat_loss
+        });
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use test_case::test_case;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Brent,
+    Hashed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub tail: usize,
+    pub period: usize,
+}
+
+impl Cycle {
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.tail {
+            n
+        } else {
+            self.tail + (n - self.tail) % self.period
+        }
+    }
+}
+
+fn fingerprint<S: Hash>(state: &S) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn advance<S: Clone>(start: &S, f: &mut impl FnMut(&S) -> S, n: usize) -> S {
+    let mut state = start.clone();
+    for _ in 0..n {
+        state = f(&state);
+    }
+    state
+}
+
+fn brent<S: Clone + Eq>(start: &S, f: &mut impl FnMut(&S) -> S) -> Cycle {
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = f(start);
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = f(&hare);
+        period += 1;
+    }
+    let mut tortoise = start.clone();
+    let mut hare = advance(start, f, period);
+    let mut tail = 0;
+    while tortoise != hare {
+        tortoise = f(&tortoise);
+        hare = f(&hare);
+        tail += 1;
+    }
+    Cycle { tail, period }
+}
+
+fn hashed<S: Clone + Eq + Hash>(start: &S, f: &mut impl FnMut(&S) -> S) -> Cycle {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    let mut state = start.clone();
+    for step in 0.. {
+        if let Some(&first) = seen.get(&fingerprint(&state)) {
+            if advance(start, f, first) == state {
+                return Cycle {
+                    tail: first,
+                    period: step - first,
+                };
+            }
+        }
+        seen.insert(fingerprint(&state), step);
+        state = f(&state);
+    }
+    unreachable!();
+}
+
+#[test_case(&0, |&x| (x + 1) % 7, Mode::Brent => Cycle { tail: 0, period: 7 })]
+#[test_case(&0, |&x| (x + 1) % 7, Mode::Hashed => Cycle { tail: 0, period: 7 })]
+#[test_case(&4, |&x| (x * x + 1) % 255, Mode::Brent => Cycle { tail: 3, period: 6 })]
+#[test_case(&4, |&x| (x * x + 1) % 255, Mode::Hashed => Cycle { tail: 3, period: 6 })]
+#[test_case(&100, |&x| if x > 10 { x - 3 } else { x + 1 }, Mode::Brent => Cycle { tail: 30, period: 4 })]
+pub fn find_cycle<S, F>(start: &S, mut f: F, mode: Mode) -> Cycle
+where
+    S: Clone + Eq + Hash,
+    F: FnMut(&S) -> S,
+{
+    match mode {
+        Mode::Brent => brent(start, &mut f),
+        Mode::Hashed => hashed(start, &mut f),
+    }
+}
+
+pub fn state_at<S, F>(start: &S, mut f: F, cycle: &Cycle, n: usize) -> S
+where
+    S: Clone,
+    F: FnMut(&S) -> S,
+{
+    advance(start, &mut f, cycle.reduce(n))
+}
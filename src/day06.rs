@@ -57,7 +57,7 @@ pub fn puzzle1(filename: &str) -> Result<i64> {
         //         ways += 1;
         //     }
         // }
-        let (x1, x2) = find_roots(-1.0, time as f64, -1.0 * distance as f64);
+        let (x1, x2) = find_roots(-1.0, time as f64, -(distance as f64));
         let ways = prev_int(x2) - next_int(x1) + 1;
         ret *= ways;
     }
@@ -99,7 +99,7 @@ pub fn puzzle2(filename: &str) -> Result<i64> {
     //         ways += 1;
     //     }
     // }
-    let (x1, x2) = find_roots(-1.0, time as f64, -1.0 * distance as f64);
+    let (x1, x2) = find_roots(-1.0, time as f64, -(distance as f64));
     let ways = prev_int(x2) - next_int(x1) + 1;
     Ok(ways)
 }
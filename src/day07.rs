@@ -1,7 +1,7 @@
 use anyhow::Result;
 use test_case::test_case;
 
-type Card = u8;
+pub type Card = u8;
 
 fn parse_card(c: char) -> Option<Card> {
     match c {
@@ -22,10 +22,27 @@ fn parse_card(c: char) -> Option<Card> {
     }
 }
 
-type Hand = [Card; 5];
+fn card_to_char(card: Card) -> char {
+    match card {
+        1 => 'j',
+        2..=9 => (b'0' + card) as char,
+        10 => 'T',
+        11 => 'J',
+        12 => 'Q',
+        13 => 'K',
+        14 => 'A',
+        _ => '?',
+    }
+}
+
+fn hand_to_string(hand: &Hand) -> String {
+    hand.iter().map(|&c| card_to_char(c)).collect()
+}
+
+pub type Hand = [Card; 5];
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Rank {
+pub enum Rank {
     HighCard,
     OnePair,
     TwoPairs,
@@ -93,20 +110,26 @@ fn rank_hand(hand: &Hand, j: bool) -> Rank {
     rank
 }
 
-#[test_case("inputs/example-07-1.txt" => matches Ok(6440))]
-#[test_case("inputs/input-07.txt" => matches Ok(249726565))]
-pub fn puzzle1(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let mut ranked: Vec<(Rank, Hand, i64)> = input
-        .into_iter()
-        .map(|(hand, bet)| (rank_hand(&hand, false), hand, bet))
-        .collect();
-    ranked.sort();
-    let mut total = 0;
-    for (i, (_, _, bet)) in ranked.iter().enumerate() {
-        total += (i as i64 + 1) * bet;
+#[test_case([3, 2, 10, 3, 13], true => [3, 2, 10, 3, 13])]
+#[test_case([10, 5, 5, 11, 5], true => [10, 5, 5, 5, 5])]
+#[test_case([13, 10, 11, 11, 10], true => [13, 10, 10, 10, 10])]
+#[test_case([2, 3, 11, 4, 5], true => [2, 3, 5, 4, 5])]
+#[test_case([11, 11, 11, 11, 11], true => [14, 14, 14, 14, 14])]
+#[test_case([11, 11, 11, 11, 11], false => [11, 11, 11, 11, 11])]
+fn best_substitution(hand: Hand, j: bool) -> Hand {
+    if !j {
+        return hand;
+    }
+    let mut counts = [0u8; 15];
+    for &card in hand.iter().filter(|&&c| c != 11) {
+        counts[card as usize] += 1;
     }
-    Ok(total)
+    let best = (2..15)
+        .filter(|&c| c != 11)
+        .max_by_key(|&c| (counts[c], c))
+        .unwrap() as Card;
+    let best = if counts[best as usize] == 0 { 14 } else { best };
+    hand.map(|c| if c == 11 { best } else { c })
 }
 
 fn downgrade(card: Card) -> Card {
@@ -116,18 +139,87 @@ fn downgrade(card: Card) -> Card {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandExplanation {
+    pub hand: Hand,
+    pub bet: i64,
+    pub rank: Rank,
+    pub substituted: Hand,
+    pub key: (Rank, Hand),
+    pub position: usize,
+    pub winnings: i64,
+}
+
+fn rank_hands(input: Vec<(Hand, i64)>, j: bool) -> Vec<HandExplanation> {
+    let mut ranked: Vec<HandExplanation> = input
+        .into_iter()
+        .map(|(hand, bet)| {
+            let rank = rank_hand(&hand, j);
+            let tiebreak = if j { hand.map(downgrade) } else { hand };
+            HandExplanation {
+                hand,
+                bet,
+                rank,
+                substituted: best_substitution(hand, j),
+                key: (rank, tiebreak),
+                position: 0,
+                winnings: 0,
+            }
+        })
+        .collect();
+    ranked.sort_by_key(|e| (e.key, e.bet));
+    for (i, e) in ranked.iter_mut().enumerate() {
+        e.position = i + 1;
+        e.winnings = e.position as i64 * e.bet;
+    }
+    ranked
+}
+
+pub fn explain_hands(filename: &str, j: bool) -> Result<Vec<HandExplanation>> {
+    let input = parse_input(filename)?;
+    Ok(rank_hands(input, j))
+}
+
+pub fn format_explanations(explanations: &[HandExplanation]) -> String {
+    let mut ret = format!(
+        "{:>5} {:5} {:>6} {:12} {:5} {:5} {:>12}\n",
+        "pos", "hand", "bet", "rank", "best", "key", "winnings"
+    );
+    for e in explanations {
+        ret += &format!(
+            "{:>5} {:5} {:>6} {:12} {:5} {:5} {:>12}\n",
+            e.position,
+            hand_to_string(&e.hand),
+            e.bet,
+            format!("{:?}", e.rank),
+            hand_to_string(&e.substituted),
+            hand_to_string(&e.key.1),
+            e.winnings
+        );
+    }
+    ret
+}
+
+pub fn print_explanations(filename: &str, j: bool) -> Result<()> {
+    print!("{}", format_explanations(&explain_hands(filename, j)?));
+    Ok(())
+}
+
+#[test_case("inputs/example-07-1.txt" => matches Ok(6440))]
+#[test_case("inputs/example-07-2.txt" => matches Ok(33))]
+#[test_case("inputs/input-07.txt" => matches Ok(249726565))]
+pub fn puzzle1(filename: &str) -> Result<i64> {
+    let ranked = explain_hands(filename, false)?;
+    Ok(ranked.iter().map(|e| e.winnings).sum())
+}
+
 #[test_case("inputs/example-07-1.txt" => matches Ok(5905))]
+#[test_case("inputs/example-07-2.txt" => matches Ok(33))]
 #[test_case("inputs/input-07.txt" => matches Ok(251135960))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let mut ranked: Vec<(Rank, Hand, i64)> = input
-        .into_iter()
-        .map(|(hand, bet)| (rank_hand(&hand, true), hand.map(downgrade), bet))
-        .collect();
-    ranked.sort();
-    let mut total = 0;
-    for (i, (_, _, bet)) in ranked.iter().enumerate() {
-        total += (i as i64 + 1) * bet;
+    let ranked = explain_hands(filename, true)?;
+    for e in &ranked {
+        debug_assert_eq!(rank_hand(&e.substituted, false), e.rank);
     }
-    Ok(total)
+    Ok(ranked.iter().map(|e| e.winnings).sum())
 }
@@ -1,10 +1,87 @@
 use anyhow::Result;
+use petgraph::graph::DiGraph;
 use std::collections::HashMap;
 use test_case::test_case;
 
-type Node = [u8; 3];
+type Node = usize;
 
-fn parse_input(filename: &str) -> Result<(Vec<bool>, HashMap<Node, (Node, Node)>)> {
+#[derive(Debug, Default)]
+pub struct Network {
+    names: Vec<String>,
+    index: HashMap<String, Node>,
+    links: Vec<(Node, Node)>,
+}
+
+impl Network {
+    fn intern(&mut self, name: &str) -> Node {
+        if let Some(&n) = self.index.get(name) {
+            return n;
+        }
+        let n = self.names.len();
+        self.names.push(name.to_string());
+        self.index.insert(name.to_string(), n);
+        n
+    }
+
+    pub fn node(&self, name: &str) -> Option<Node> {
+        self.index.get(name).copied()
+    }
+
+    pub fn name(&self, node: Node) -> &str {
+        &self.names[node]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    fn select(&self, selector: &NodeSelector) -> Vec<Node> {
+        (0..self.len())
+            .filter(|&n| selector.matches(self.name(n)))
+            .collect()
+    }
+
+    pub fn to_graph(&self) -> DiGraph<String, char> {
+        let mut graph = DiGraph::new();
+        let ids: Vec<_> = self
+            .names
+            .iter()
+            .map(|n| graph.add_node(n.clone()))
+            .collect();
+        for (n, &(left, right)) in self.links.iter().enumerate() {
+            graph.add_edge(ids[n], ids[left], 'L');
+            graph.add_edge(ids[n], ids[right], 'R');
+        }
+        graph
+    }
+
+    pub fn to_dot(&self) -> String {
+        format!("{}", petgraph::dot::Dot::new(&self.to_graph()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeSelector {
+    Prefix(String),
+    Suffix(String),
+    Names(Vec<String>),
+}
+
+impl NodeSelector {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NodeSelector::Prefix(p) => name.starts_with(p.as_str()),
+            NodeSelector::Suffix(s) => name.ends_with(s.as_str()),
+            NodeSelector::Names(names) => names.iter().any(|n| n == name),
+        }
+    }
+}
+
+fn parse_input(filename: &str) -> Result<(Vec<bool>, Network)> {
     let input = std::fs::read_to_string(filename)?;
     let (dirs, nodes) = input.split_once("\n\n").unwrap();
     let dirs: Vec<bool> = dirs
@@ -15,66 +92,162 @@ fn parse_input(filename: &str) -> Result<(Vec<bool>, HashMap<Node, (Node, Node)>
             _ => panic!("invalid direction"),
         })
         .collect();
-    let nodes: HashMap<Node, (Node, Node)> = nodes
-        .lines()
-        .map(|line| {
-            let (node, branch) = line.split_once(" = ").unwrap();
-            let node: Node = node.as_bytes().try_into().unwrap();
-            let (left, right) = branch.split_once(", ").unwrap();
-            let left: Node = left.trim_start_matches('(').as_bytes().try_into().unwrap();
-            let right: Node = right.trim_end_matches(')').as_bytes().try_into().unwrap();
-            (node, (left, right))
-        })
-        .collect();
-    Ok((dirs, nodes))
+    let mut network = Network::default();
+    let mut links = HashMap::new();
+    for line in nodes.lines() {
+        let (node, branch) = line.split_once(" = ").unwrap();
+        let (left, right) = branch.split_once(", ").unwrap();
+        let node = network.intern(node);
+        let left = network.intern(left.trim_start_matches('('));
+        let right = network.intern(right.trim_end_matches(')'));
+        links.insert(node, (left, right));
+    }
+    for n in 0..network.len() {
+        let Some(&link) = links.get(&n) else {
+            return Err(anyhow::anyhow!("Undefined node {}", network.name(n)));
+        };
+        network.links.push(link);
+    }
+    Ok((dirs, network))
+}
+
+#[test_case("inputs/example-08-5.txt" => matches Ok(s) if s == std::fs::read_to_string("inputs/example-08-5.dot").unwrap())]
+pub fn export_dot(filename: &str) -> Result<String> {
+    let (_, network) = parse_input(filename)?;
+    Ok(network.to_dot())
 }
 
 #[test_case("inputs/example-08-1.txt" => matches Ok(2))]
 #[test_case("inputs/example-08-2.txt" => matches Ok(6))]
+#[test_case("inputs/example-08-5.txt" => matches Ok(2))]
 #[test_case("inputs/input-08.txt" => matches Ok(18113))]
 pub fn puzzle1(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let (dirs, nodes) = input;
-    let mut node = [b'A', b'A', b'A'];
-    let mut count = 0;
-    for &r in dirs.iter().cycle() {
-        let (left, right) = nodes[&node];
-        node = if r { right } else { left };
-        count += 1;
-        if node == [b'Z', b'Z', b'Z'] {
-            break;
+    let start = NodeSelector::Names(vec!["AAA".to_string()]);
+    let goal = NodeSelector::Names(vec!["ZZZ".to_string()]);
+    let cycles = ghost_cycles_with(filename, &start, &goal)?;
+    earliest_common_step(&cycles)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GhostCycle {
+    pub tail: usize,
+    pub len: usize,
+    pub tail_hits: Vec<usize>,
+    pub hits: Vec<usize>,
+}
+
+impl GhostCycle {
+    fn hits_at(&self, step: usize) -> bool {
+        if step < self.tail {
+            self.tail_hits.contains(&step)
+        } else {
+            self.hits.contains(&((step - self.tail) % self.len))
         }
     }
-    Ok(count)
 }
 
-#[test_case("inputs/example-08-3.txt" => matches Ok(6))]
-#[test_case("inputs/input-08.txt" => matches Ok(12315788159977))]
-pub fn puzzle2(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let (dirs, nodes) = input;
-    let mut cur: Vec<Node> = nodes.keys().filter(|k| k[2] == b'A').cloned().collect();
-    let mut count = 0;
-    let mut lens = Vec::new();
-    for &r in dirs.iter().cycle() {
-        for n in cur.iter_mut() {
-            let (left, right) = nodes[n];
-            *n = if r { right } else { left };
+fn find_cycle(dirs: &[bool], network: &Network, start: Node, goal: &[bool]) -> GhostCycle {
+    let mut seen: HashMap<(Node, usize), usize> = HashMap::new();
+    let mut goals = Vec::new();
+    let mut node = start;
+    let mut step = 0;
+    let tail = loop {
+        let state = (node, step % dirs.len());
+        if let Some(&first) = seen.get(&state) {
+            break first;
         }
-        count += 1;
-        cur.retain(|c| {
-            if c[2] == b'Z' {
-                lens.push(count);
-                false
-            } else {
-                true
-            }
-        });
-        if cur.is_empty() {
-            break;
+        seen.insert(state, step);
+        if goal[node] {
+            goals.push(step);
         }
+        let (left, right) = network.links[node];
+        node = if dirs[step % dirs.len()] { right } else { left };
+        step += 1;
+    };
+    let (tail_hits, hits) = goals.into_iter().partition(|&g| g < tail);
+    GhostCycle {
+        tail,
+        len: step - tail,
+        tail_hits,
+        hits: hits.into_iter().map(|g: usize| g - tail).collect(),
     }
-    println!("{:?}", lens);
-    let lcm = lens.into_iter().fold(1, num::integer::lcm);
-    Ok(lcm)
+}
+
+pub fn ghost_cycles_with(
+    filename: &str,
+    start: &NodeSelector,
+    goal: &NodeSelector,
+) -> Result<Vec<GhostCycle>> {
+    let (dirs, network) = parse_input(filename)?;
+    let starts = network.select(start);
+    if starts.is_empty() {
+        return Err(anyhow::anyhow!("No start nodes"));
+    }
+    let goal: Vec<bool> = network.names.iter().map(|n| goal.matches(n)).collect();
+    let ret = starts
+        .into_iter()
+        .map(|start| find_cycle(&dirs, &network, start, &goal))
+        .collect();
+    Ok(ret)
+}
+
+pub fn ghost_cycles(filename: &str) -> Result<Vec<GhostCycle>> {
+    let start = NodeSelector::Suffix("A".to_string());
+    let goal = NodeSelector::Suffix("Z".to_string());
+    ghost_cycles_with(filename, &start, &goal)
+}
+
+#[test_case((2, 3), (3, 5) => Some((8, 15)))]
+#[test_case((1, 4), (3, 6) => Some((9, 12)))]
+#[test_case((1, 4), (2, 6) => None)]
+#[test_case((0, 7), (0, 7) => Some((0, 7)))]
+fn solve_crt((a1, m1): (i128, i128), (a2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    use num::Integer;
+    let e = m1.extended_gcd(&m2);
+    if (a2 - a1) % e.gcd != 0 {
+        return None;
+    }
+    let lcm = m1 / e.gcd * m2;
+    let t = ((a2 - a1) / e.gcd * e.x).rem_euclid(m2 / e.gcd);
+    Some(((a1 + m1 * t).rem_euclid(lcm), lcm))
+}
+
+pub fn earliest_common_step(cycles: &[GhostCycle]) -> Result<i64> {
+    let mut best: Option<i128> = cycles
+        .iter()
+        .flat_map(|c| c.tail_hits.iter())
+        .filter(|&&t| t > 0 && cycles.iter().all(|c| c.hits_at(t)))
+        .map(|&t| t as i128)
+        .min();
+    let lower = cycles.iter().map(|c| c.tail).max().unwrap_or(0).max(1) as i128;
+    let mut combos: Vec<(i128, i128)> = vec![(0, 1)];
+    for c in cycles {
+        combos = combos
+            .iter()
+            .flat_map(|&acc| {
+                c.hits
+                    .iter()
+                    .filter_map(move |&h| solve_crt(acc, ((c.tail + h) as i128, c.len as i128)))
+            })
+            .collect();
+    }
+    for (x, m) in combos {
+        let x = if x < lower {
+            x + (lower - x + m - 1) / m * m
+        } else {
+            x
+        };
+        best = Some(best.map_or(x, |b| b.min(x)));
+    }
+    let best = best.ok_or_else(|| anyhow::anyhow!("No common step for all ghosts"))?;
+    Ok(best.try_into()?)
+}
+
+#[test_case("inputs/example-08-3.txt" => matches Ok(6))]
+#[test_case("inputs/example-08-4.txt" => matches Err(_))]
+#[test_case("inputs/example-08-6.txt" => matches Ok(6))]
+#[test_case("inputs/input-08.txt" => matches Ok(12315788159977))]
+pub fn puzzle2(filename: &str) -> Result<i64> {
+    let cycles = ghost_cycles(filename)?;
+    earliest_common_step(&cycles)
 }
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use num::{BigInt, BigRational, One, Zero};
 use test_case::test_case;
 
 fn parse_input(filename: &str) -> Result<Vec<Vec<i64>>> {
@@ -10,44 +11,98 @@ fn parse_input(filename: &str) -> Result<Vec<Vec<i64>>> {
     Ok(ret)
 }
 
-fn derive(row: &[i64]) -> Vec<i64> {
-    row.windows(2).map(|w| w[1] - w[0]).collect()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polynomial {
+    pub degree: usize,
+    pub exact: bool,
+    len: usize,
+    diffs: Vec<BigInt>,
 }
 
-fn predict_next(row: &[i64]) -> i64 {
-    if row.iter().all(|&v| v == 0) {
-        0
-    } else {
-        row.last().unwrap() + predict_next(&derive(row))
+impl Polynomial {
+    pub fn at(&self, x: &BigInt) -> BigInt {
+        let mut ret = BigInt::zero();
+        let mut binom = BigInt::one();
+        for (k, d) in self.diffs.iter().enumerate() {
+            ret += d * &binom;
+            binom = binom * (x - k) / (k + 1);
+        }
+        ret
+    }
+
+    pub fn ahead(&self, k: i64) -> BigInt {
+        self.at(&BigInt::from(self.len as i64 - 1 + k))
+    }
+
+    pub fn back(&self, k: i64) -> BigInt {
+        self.at(&BigInt::from(-k))
+    }
+
+    pub fn coefficients(&self) -> Vec<BigRational> {
+        let mut ret = vec![BigRational::zero(); self.diffs.len()];
+        let mut falling = vec![BigRational::one()];
+        for (k, d) in self.diffs.iter().enumerate() {
+            if k > 0 {
+                let mut next = vec![BigRational::zero(); k + 1];
+                for (i, c) in falling.iter().enumerate() {
+                    next[i + 1] += c / BigInt::from(k);
+                    next[i] -= c * BigInt::from(k - 1) / BigInt::from(k);
+                }
+                falling = next;
+            }
+            for (i, c) in falling.iter().enumerate() {
+                ret[i] += c * d;
+            }
+        }
+        ret
     }
 }
 
+#[test_case(&[0, 3, 6, 9, 12, 15] => matches Polynomial { degree: 1, exact: true, .. })]
+#[test_case(&[1, 3, 6, 10, 15, 21] => matches Polynomial { degree: 2, exact: true, .. })]
+#[test_case(&[0, 0, 0] => matches Polynomial { degree: 0, exact: true, .. })]
+#[test_case(&[1, 2, 4, 8, 16] => matches Polynomial { degree: 4, exact: false, .. })]
+pub fn fit(seq: &[i64]) -> Polynomial {
+    let mut row: Vec<BigInt> = seq.iter().map(|&v| BigInt::from(v)).collect();
+    let mut diffs = Vec::new();
+    let mut exact = false;
+    for n in (1..=row.len()).rev() {
+        diffs.push(row[0].clone());
+        if n >= 2 && row[1..n].iter().all(|v| *v == row[0]) {
+            exact = true;
+            break;
+        }
+        for i in 0..n - 1 {
+            row[i] = &row[i + 1] - &row[i];
+        }
+    }
+    Polynomial {
+        degree: diffs.len().saturating_sub(1),
+        exact,
+        len: seq.len(),
+        diffs,
+    }
+}
+
+#[test_case(&[0, 1, 4, 9], 1_000_000_000_000 => BigInt::from(1_000_000_000_000_000_000_000_000_i128))]
+#[test_case(&[10, 13, 16, 21, 30, 45], 6 => BigInt::from(68))]
+#[test_case(&[10, 13, 16, 21, 30, 45], -1 => BigInt::from(5))]
+pub fn extrapolate(seq: &[i64], x: i64) -> BigInt {
+    fit(seq).at(&BigInt::from(x))
+}
+
 #[test_case("inputs/example-09-1.txt" => matches Ok(114))]
 #[test_case("inputs/input-09.txt" => matches Ok(2075724761))]
 pub fn puzzle1(filename: &str) -> Result<i64> {
     let input = parse_input(filename)?;
-    let mut total = 0;
-    for row in input {
-        total += predict_next(&row);
-    }
-    Ok(total)
-}
-
-fn predict_prev(row: &[i64]) -> i64 {
-    if row.iter().all(|&v| v == 0) {
-        0
-    } else {
-        row.first().unwrap() - predict_prev(&derive(row))
-    }
+    let total: BigInt = input.iter().map(|row| fit(row).ahead(1)).sum();
+    Ok(i64::try_from(total)?)
 }
 
 #[test_case("inputs/example-09-1.txt" => matches Ok(2))]
 #[test_case("inputs/input-09.txt" => matches Ok(1072))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
     let input = parse_input(filename)?;
-    let mut total = 0;
-    for row in input {
-        total += predict_prev(&row);
-    }
-    Ok(total)
+    let total: BigInt = input.iter().map(|row| fit(row).back(1)).sum();
+    Ok(i64::try_from(total)?)
 }
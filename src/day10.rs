@@ -4,7 +4,7 @@ use std::collections::HashSet;
 use test_case::test_case;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Pipe {
+pub enum Pipe {
     NS,
     EW,
     NE,
@@ -36,26 +36,17 @@ fn parse_input(filename: &str) -> Result<Matrix<Pipe>> {
     Ok(ret)
 }
 
-#[allow(dead_code)]
-fn print_input(input: &Matrix<Pipe>) {
-    for y in 0..input.rows {
-        for x in 0..input.columns {
-            print!(
-                "{}",
-                match input[(y, x)] {
-                    Pipe::NS => '┃',
-                    Pipe::EW => '━',
-                    Pipe::NE => '┗',
-                    Pipe::NW => '┛',
-                    Pipe::SW => '┓',
-                    Pipe::SE => '┏',
-                    Pipe::G => '▦',
-                    Pipe::S => '╳',
-                    Pipe::O => ' ',
-                }
-            );
-        }
-        println!();
+fn pipe_char(pipe: Pipe) -> char {
+    match pipe {
+        Pipe::NS => '┃',
+        Pipe::EW => '━',
+        Pipe::NE => '┗',
+        Pipe::NW => '┛',
+        Pipe::SW => '┓',
+        Pipe::SE => '┏',
+        Pipe::G => '▦',
+        Pipe::S => '╳',
+        Pipe::O => ' ',
     }
 }
 
@@ -77,34 +68,166 @@ fn next_pipe(dir: (isize, isize), cur: Pipe) -> Option<(isize, isize)> {
     }
 }
 
-#[test_case("inputs/example-10-1.txt" => matches Ok(8))]
-#[test_case("inputs/input-10.txt" => matches Ok(6927))]
-pub fn puzzle1(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let (startpos, _) = input.items().find(|&(_, &v)| v == Pipe::S).unwrap();
+fn pipe_from_openings(a: (isize, isize), b: (isize, isize)) -> Option<Pipe> {
+    [Pipe::NS, Pipe::EW, Pipe::NE, Pipe::NW, Pipe::SW, Pipe::SE]
+        .into_iter()
+        .find(|&p| next_pipe((-a.0, -a.1), p) == Some(b))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoopError {
+    NoStart,
+    MultipleStarts(Vec<(usize, usize)>),
+    DeadEnd((usize, usize)),
+    Branch((usize, usize)),
+    AmbiguousStart(Vec<Pipe>),
+}
+
+impl std::fmt::Display for LoopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoopError::NoStart => write!(f, "No start tile"),
+            LoopError::MultipleStarts(p) => write!(f, "Multiple start tiles at {:?}", p),
+            LoopError::DeadEnd(p) => write!(f, "Pipe at {:?} leads nowhere", p),
+            LoopError::Branch(p) => write!(f, "Loop branches at {:?}", p),
+            LoopError::AmbiguousStart(p) => write!(f, "Start tile could be any of {:?}", p),
+        }
+    }
+}
+
+impl std::error::Error for LoopError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeLoop {
+    pub start_pipe: Pipe,
+    pub tiles: Vec<(usize, usize)>,
+}
+
+fn walk_loop(
+    input: &Matrix<Pipe>,
+    startpos: (usize, usize),
+    out: (isize, isize),
+    back: (isize, isize),
+) -> Result<Vec<(usize, usize)>, LoopError> {
+    let mut ret = vec![startpos];
+    let mut curdir = out;
     let mut curpos = startpos;
-    let mut curdir = directions::N;
-    for dir in directions::DIRECTIONS_4 {
-        if input
-            .move_in_direction(startpos, dir)
-            .and_then(|pos| next_pipe(dir, input[pos]))
-            .is_some()
-        {
-            curdir = dir;
-            curpos = input.move_in_direction(startpos, dir).unwrap();
+    loop {
+        let next = input
+            .move_in_direction(curpos, curdir)
+            .ok_or(LoopError::DeadEnd(curpos))?;
+        if next == startpos {
             break;
         }
+        if ret.len() > input.rows * input.columns {
+            return Err(LoopError::Branch(next));
+        }
+        curdir = next_pipe(curdir, input[next]).ok_or(LoopError::DeadEnd(curpos))?;
+        ret.push(next);
+        curpos = next;
+    }
+    if curdir != (-back.0, -back.1) {
+        return Err(LoopError::Branch(startpos));
+    }
+    Ok(ret)
+}
+
+pub fn extract_loop(input: &Matrix<Pipe>) -> Result<PipeLoop, LoopError> {
+    let starts: Vec<_> = input
+        .items()
+        .filter(|&(_, &v)| v == Pipe::S)
+        .map(|(p, _)| p)
+        .collect();
+    let startpos = match starts[..] {
+        [] => return Err(LoopError::NoStart),
+        [p] => p,
+        _ => return Err(LoopError::MultipleStarts(starts)),
+    };
+    let connecting: Vec<_> = directions::DIRECTIONS_4
+        .into_iter()
+        .filter(|&dir| {
+            input
+                .move_in_direction(startpos, dir)
+                .and_then(|pos| next_pipe(dir, input[pos]))
+                .is_some()
+        })
+        .collect();
+    if connecting.len() < 2 {
+        return Err(LoopError::DeadEnd(startpos));
+    }
+    let mut found = Vec::new();
+    let mut first_err = None;
+    for (i, &a) in connecting.iter().enumerate() {
+        for &b in &connecting[i + 1..] {
+            let Some(start_pipe) = pipe_from_openings(a, b) else {
+                continue;
+            };
+            match walk_loop(input, startpos, a, b) {
+                Ok(tiles) => found.push(PipeLoop { start_pipe, tiles }),
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
     }
-    let mut count = 0;
-    while curpos != startpos {
-        count += 1;
-        curdir = next_pipe(curdir, input[curpos]).unwrap();
-        curpos = input.move_in_direction(curpos, curdir).unwrap();
+    match found.len() {
+        0 if connecting.len() > 2 => Err(LoopError::Branch(startpos)),
+        0 => Err(first_err.unwrap()),
+        1 => Ok(found.pop().unwrap()),
+        _ => Err(LoopError::AmbiguousStart(
+            found.into_iter().map(|l| l.start_pipe).collect(),
+        )),
     }
-    let ret = (count + 1) / 2;
+}
+
+fn load_loop(filename: &str) -> Result<(Matrix<Pipe>, PipeLoop)> {
+    let mut input = parse_input(filename)?;
+    let pipeloop = extract_loop(&input)?;
+    input[pipeloop.tiles[0]] = pipeloop.start_pipe;
+    Ok((input, pipeloop))
+}
+
+#[test_case("inputs/example-10-1.txt" => matches Ok(8))]
+#[test_case("inputs/input-10.txt" => matches Ok(6927))]
+#[test_case("inputs/example-10-3.txt" => matches Err(e) if e.downcast_ref() == Some(&LoopError::AmbiguousStart(vec![Pipe::NE, Pipe::SW])))]
+#[test_case("inputs/example-10-4.txt" => matches Err(e) if e.downcast_ref() == Some(&LoopError::DeadEnd((2, 3))))]
+pub fn puzzle1(filename: &str) -> Result<i64> {
+    let (_, pipeloop) = load_loop(filename)?;
+    let ret = pipeloop.tiles.len() as i64 / 2;
     Ok(ret)
 }
 
+fn interior_area(looppipes: &[(usize, usize)]) -> i64 {
+    let twice_area = looppipes
+        .iter()
+        .zip(looppipes.iter().cycle().skip(1))
+        .map(|(&(r1, c1), &(r2, c2))| c1 as i64 * r2 as i64 - c2 as i64 * r1 as i64)
+        .sum::<i64>()
+        .abs();
+    (twice_area - looppipes.len() as i64) / 2 + 1
+}
+
+fn enclosed_tiles(input: &Matrix<Pipe>, looppipes: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut on_loop = Matrix::new(input.rows, input.columns, false);
+    for &pos in looppipes {
+        on_loop[pos] = true;
+    }
+    let mut ret = Vec::new();
+    for row in 0..input.rows {
+        let mut inside = false;
+        for col in 0..input.columns {
+            if on_loop[(row, col)] {
+                if matches!(input[(row, col)], Pipe::NS | Pipe::NE | Pipe::NW) {
+                    inside = !inside;
+                }
+            } else if inside {
+                ret.push((row, col));
+            }
+        }
+    }
+    ret
+}
+
 #[allow(clippy::identity_op)]
 fn expand_pipes(input: &Matrix<Pipe>) -> Matrix<Pipe> {
     let mut ret = Matrix::new(input.rows * 3, input.columns * 3, Pipe::O);
@@ -144,18 +267,7 @@ fn expand_pipes(input: &Matrix<Pipe>) -> Matrix<Pipe> {
                 Pipe::G => {
                     ret[(row * 3 + 1, col * 3 + 1)] = Pipe::G;
                 }
-                Pipe::S => {
-                    ret[(row * 3 + 0, col * 3 + 0)] = Pipe::S;
-                    ret[(row * 3 + 0, col * 3 + 1)] = Pipe::S;
-                    ret[(row * 3 + 0, col * 3 + 2)] = Pipe::S;
-                    ret[(row * 3 + 1, col * 3 + 0)] = Pipe::S;
-                    ret[(row * 3 + 1, col * 3 + 1)] = Pipe::S;
-                    ret[(row * 3 + 1, col * 3 + 2)] = Pipe::S;
-                    ret[(row * 3 + 2, col * 3 + 0)] = Pipe::S;
-                    ret[(row * 3 + 2, col * 3 + 1)] = Pipe::S;
-                    ret[(row * 3 + 2, col * 3 + 2)] = Pipe::S;
-                }
-                Pipe::O => {}
+                Pipe::S | Pipe::O => {}
             }
         }
     }
@@ -165,29 +277,32 @@ fn expand_pipes(input: &Matrix<Pipe>) -> Matrix<Pipe> {
 #[test_case("inputs/example-10-2.txt" => matches Ok(10))]
 #[test_case("inputs/input-10.txt" => matches Ok(467))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
-    let mut input = parse_input(filename)?;
+    let classes = tile_classes(filename)?;
+    let ret = classes
+        .values()
+        .filter(|&&c| c == TileClass::Inside)
+        .count() as i64;
+    Ok(ret)
+}
+
+#[test_case("inputs/example-10-2.txt" => matches Ok(10))]
+#[test_case("inputs/input-10.txt" => matches Ok(467))]
+pub fn puzzle2_pick(filename: &str) -> Result<i64> {
+    let (_, pipeloop) = load_loop(filename)?;
+    Ok(interior_area(&pipeloop.tiles))
+}
+
+pub fn enclosed(filename: &str) -> Result<Vec<(usize, usize)>> {
+    let (input, pipeloop) = load_loop(filename)?;
+    Ok(enclosed_tiles(&input, &pipeloop.tiles))
+}
+
+#[test_case("inputs/example-10-2.txt" => matches Ok(10))]
+#[test_case("inputs/input-10.txt" => matches Ok(467))]
+pub fn puzzle2_expanded(filename: &str) -> Result<i64> {
     // find loop
-    let (startpos, _) = input.items().find(|&(_, &v)| v == Pipe::S).unwrap();
-    let mut looppipes = HashSet::new();
-    looppipes.insert(startpos);
-    let mut curpos = startpos;
-    let mut curdir = directions::N;
-    for dir in directions::DIRECTIONS_4 {
-        if input
-            .move_in_direction(startpos, dir)
-            .and_then(|pos| next_pipe(dir, input[pos]))
-            .is_some()
-        {
-            curdir = dir;
-            curpos = input.move_in_direction(startpos, dir).unwrap();
-            break;
-        }
-    }
-    while curpos != startpos {
-        looppipes.insert(curpos);
-        curdir = next_pipe(curdir, input[curpos]).unwrap();
-        curpos = input.move_in_direction(curpos, curdir).unwrap();
-    }
+    let (mut input, pipeloop) = load_loop(filename)?;
+    let looppipes: HashSet<_> = pipeloop.tiles.into_iter().collect();
     // delete everything else except loop
     for pos in input.keys() {
         if !looppipes.contains(&pos) {
@@ -207,3 +322,97 @@ pub fn puzzle2(filename: &str) -> Result<i64> {
     let ret = expanded.values().filter(|&&v| v == Pipe::G).count() as i64;
     Ok(ret)
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileClass {
+    Loop,
+    Inside,
+    Outside,
+    Junk,
+}
+
+fn classify_tiles(input: &Matrix<Pipe>, pipeloop: &PipeLoop) -> Matrix<TileClass> {
+    let mut ret = Matrix::new(input.rows, input.columns, TileClass::Outside);
+    for (pos, &pipe) in input.items() {
+        if pipe != Pipe::G {
+            ret[pos] = TileClass::Junk;
+        }
+    }
+    for pos in enclosed_tiles(input, &pipeloop.tiles) {
+        ret[pos] = TileClass::Inside;
+    }
+    for &pos in &pipeloop.tiles {
+        ret[pos] = TileClass::Loop;
+    }
+    ret
+}
+
+pub fn tile_classes(filename: &str) -> Result<Matrix<TileClass>> {
+    let (input, pipeloop) = load_loop(filename)?;
+    Ok(classify_tiles(&input, &pipeloop))
+}
+
+#[test_case("inputs/example-10-2.txt" => matches Ok(s) if s == std::fs::read_to_string("inputs/example-10-2.ansi").unwrap())]
+pub fn render_ansi(filename: &str) -> Result<String> {
+    let (input, pipeloop) = load_loop(filename)?;
+    let classes = classify_tiles(&input, &pipeloop);
+    let mut ret = String::new();
+    for row in 0..input.rows {
+        for col in 0..input.columns {
+            let style = match classes[(row, col)] {
+                TileClass::Loop => "1;33",
+                TileClass::Inside => "1;32;42",
+                TileClass::Outside => "2",
+                TileClass::Junk => "31",
+            };
+            ret += &format!("\x1b[{}m{}", style, pipe_char(input[(row, col)]));
+        }
+        ret += "\x1b[0m\n";
+    }
+    Ok(ret)
+}
+
+#[test_case("inputs/example-10-2.txt" => matches Ok(s) if s == std::fs::read_to_string("inputs/example-10-2.svg").unwrap())]
+pub fn render_svg(filename: &str) -> Result<String> {
+    const CELL: usize = 10;
+    let (input, pipeloop) = load_loop(filename)?;
+    let classes = classify_tiles(&input, &pipeloop);
+    let mut ret = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        input.columns * CELL,
+        input.rows * CELL
+    );
+    for ((row, col), &class) in classes.items() {
+        let (fill, stroke) = match class {
+            TileClass::Loop => ("#fff7d6", "#d08000"),
+            TileClass::Inside => ("#4caf50", "#1b5e20"),
+            TileClass::Outside => ("#eeeeee", "#bbbbbb"),
+            TileClass::Junk => ("#eeeeee", "#e57373"),
+        };
+        let (x, y) = (col * CELL, row * CELL);
+        ret += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+            x, y, CELL, CELL, fill
+        );
+        let (cx, cy) = (x + CELL / 2, y + CELL / 2);
+        for dir in directions::DIRECTIONS_4 {
+            if next_pipe((-dir.0, -dir.1), input[(row, col)]).is_some() {
+                ret += &format!(
+                    "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+                    cx,
+                    cy,
+                    (cx as isize + dir.1 * CELL as isize / 2),
+                    (cy as isize + dir.0 * CELL as isize / 2),
+                    stroke
+                );
+            }
+        }
+    }
+    ret += "</svg>\n";
+    Ok(ret)
+}
+
+pub fn write_svg(filename: &str, output: &str) -> Result<()> {
+    std::fs::write(output, render_svg(filename)?)?;
+    Ok(())
+}
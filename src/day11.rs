@@ -16,27 +16,126 @@ fn parse_input(filename: &str) -> Result<Vec<(i64, i64)>> {
     Ok(ret)
 }
 
-fn expand(input: &mut [(i64, i64)], count: i64) {
-    let mut prevx = 0;
-    let mut xinc = 0;
-    input.sort_by_key(|(x, _)| *x);
-    for (x, _) in input.iter_mut() {
-        if *x != prevx {
-            xinc += (*x - prevx - 1) * count;
-            prevx = *x;
+fn expand_axis(coords: &[i64], factor: i128) -> Result<Vec<i128>> {
+    let overflow = || anyhow::anyhow!("Overflow while expanding");
+    let mut order: Vec<usize> = (0..coords.len()).collect();
+    order.sort_by_key(|&i| coords[i]);
+    let mut ret = vec![0; coords.len()];
+    let mut prev = -1;
+    let mut empty: i128 = 0;
+    for i in order {
+        if coords[i] > prev {
+            empty += (coords[i] - prev - 1) as i128;
+            prev = coords[i];
         }
-        *x += xinc;
+        let extra = factor
+            .checked_sub(1)
+            .and_then(|f| empty.checked_mul(f))
+            .ok_or_else(overflow)?;
+        ret[i] = extra.checked_add(coords[i] as i128).ok_or_else(overflow)?;
     }
-    let mut prevy = 0;
-    let mut yinc = 0;
-    input.sort_by_key(|(_, y)| *y);
-    for (_, y) in input.iter_mut() {
-        if *y != prevy {
-            yinc += (*y - prevy - 1) * count;
-            prevy = *y;
+    Ok(ret)
+}
+
+#[test_case(&[(1, 0), (3, 1)], 2, 2 => matches Ok(v) if v == [(2, 0), (5, 1)])]
+#[test_case(&[(2, 3), (2, 0)], 10, 1 => matches Ok(v) if v == [(20, 3), (20, 0)])]
+#[test_case(&[(0, 0), (4, 4)], 3, 2 => matches Ok(v) if v == [(0, 0), (10, 7)])]
+pub fn expand_galaxies(
+    galaxies: &[(i64, i64)],
+    col_factor: i128,
+    row_factor: i128,
+) -> Result<Vec<(i128, i128)>> {
+    let xs: Vec<i64> = galaxies.iter().map(|&(x, _)| x).collect();
+    let ys: Vec<i64> = galaxies.iter().map(|&(_, y)| y).collect();
+    let xs = expand_axis(&xs, col_factor)?;
+    let ys = expand_axis(&ys, row_factor)?;
+    Ok(xs.into_iter().zip(ys).collect())
+}
+
+fn axis_distance_sum(mut values: Vec<i128>) -> Result<i128> {
+    let overflow = || anyhow::anyhow!("Overflow while summing distances");
+    values.sort_unstable();
+    let mut prefix: i128 = 0;
+    let mut ret: i128 = 0;
+    for (i, v) in values.into_iter().enumerate() {
+        let d = v
+            .checked_mul(i as i128)
+            .and_then(|t| t.checked_sub(prefix))
+            .ok_or_else(overflow)?;
+        ret = ret.checked_add(d).ok_or_else(overflow)?;
+        prefix = prefix.checked_add(v).ok_or_else(overflow)?;
+    }
+    Ok(ret)
+}
+
+pub fn distance_sum(points: &[(i128, i128)]) -> Result<i128> {
+    let xs = axis_distance_sum(points.iter().map(|&(x, _)| x).collect())?;
+    let ys = axis_distance_sum(points.iter().map(|&(_, y)| y).collect())?;
+    xs.checked_add(ys)
+        .ok_or_else(|| anyhow::anyhow!("Overflow while summing distances"))
+}
+
+fn manhattan(a: (i128, i128), b: (i128, i128)) -> i128 {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+}
+
+#[test_case(&[(0, 0), (5, 5), (1, 2), (9, 0)] => Some((0, 2, 3)))]
+#[test_case(&[(3, 3), (7, 1), (3, 3)] => Some((0, 2, 0)))]
+#[test_case(&[(1, 1)] => None)]
+pub fn nearest_pair(points: &[(i128, i128)]) -> Option<(usize, usize, i128)> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by_key(|&i| points[i]);
+    let mut best: Option<(usize, usize, i128)> = None;
+    let mut active = std::collections::BTreeSet::new();
+    let mut tail = 0;
+    for &i in order.iter() {
+        let (x, y) = points[i];
+        if let Some((_, _, d)) = best {
+            while points[order[tail]].0 < x - d {
+                let j = order[tail];
+                active.remove(&(points[j].1, j));
+                tail += 1;
+            }
+        }
+        let range = match best {
+            Some((_, _, d)) => (y - d, 0)..=(y + d, usize::MAX),
+            None => (i128::MIN, 0)..=(i128::MAX, usize::MAX),
+        };
+        for &(_, j) in active.range(range) {
+            let d = manhattan(points[i], points[j]);
+            if best.is_none_or(|(_, _, b)| d < b) {
+                best = Some((j.min(i), j.max(i), d));
+            }
         }
-        *y += yinc;
+        if let Some((_, _, 0)) = best {
+            break;
+        }
+        active.insert((y, i));
     }
+    best
+}
+
+#[test_case(&[(0, 0), (5, 5), (1, 2), (9, 0)] => Some((0, 1, 10)))]
+#[test_case(&[] => None)]
+pub fn farthest_pair(points: &[(i128, i128)]) -> Option<(usize, usize, i128)> {
+    let extremes = |f: &dyn Fn((i128, i128)) -> i128| {
+        let lo = (0..points.len()).min_by_key(|&i| f(points[i]))?;
+        let hi = (0..points.len()).max_by_key(|&i| f(points[i]))?;
+        Some((lo.min(hi), lo.max(hi), f(points[hi]) - f(points[lo])))
+    };
+    let sum = extremes(&|(x, y)| x + y)?;
+    let diff = extremes(&|(x, y)| x - y)?;
+    Some(if sum.2 >= diff.2 { sum } else { diff })
+}
+
+#[test_case("inputs/example-11-1.txt", 10, 1 => matches Ok(706))]
+#[test_case("inputs/example-11-1.txt", 2, 2 => matches Ok(374))]
+#[test_case("inputs/example-11-1.txt", i128::MAX, 2 => matches Err(_))]
+#[test_case("inputs/example-11-1.txt", i128::MIN, 2 => matches Err(_))]
+pub fn galaxy_distances(filename: &str, col_factor: i128, row_factor: i128) -> Result<i128> {
+    let input = parse_input(filename)?;
+    let points = expand_galaxies(&input, col_factor, row_factor)?;
+    distance_sum(&points)
 }
 
 #[test_case("inputs/example-11-1.txt", 1 => matches Ok(374))]
@@ -44,16 +143,7 @@ fn expand(input: &mut [(i64, i64)], count: i64) {
 #[test_case("inputs/example-11-1.txt", 99 => matches Ok(8410))]
 #[test_case("inputs/input-11.txt", 999999 => matches Ok(827009909817))]
 pub fn puzzle1and2(filename: &str, count: i64) -> Result<i64> {
-    let mut input = parse_input(filename)?;
-    expand(&mut input, count);
-    let tot = input
-        .iter()
-        .enumerate()
-        .flat_map(|(i, &(g1x, g1y))| {
-            input[i + 1..]
-                .iter()
-                .map(move |&(g2x, g2y)| (g1x - g2x).abs() + (g1y - g2y).abs())
-        })
-        .sum();
-    Ok(tot)
+    let factor = count as i128 + 1;
+    let tot = galaxy_distances(filename, factor, factor)?;
+    Ok(tot.try_into()?)
 }
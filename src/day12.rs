@@ -1,37 +1,48 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use num::{BigUint, One, ToPrimitive, Zero};
+use std::collections::{HashMap, HashSet};
+use std::ops::AddAssign;
 use test_case::test_case;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Spring {
+pub enum Spring {
     Ope,
     Dam,
     Unk,
 }
 
+fn parse_line(line: &str) -> Result<(Vec<Spring>, Vec<i64>)> {
+    let (springs, groups) = line.split_once(' ').unwrap();
+    let springs = springs
+        .chars()
+        .map(|c| match c {
+            '.' => Spring::Ope,
+            '#' => Spring::Dam,
+            '?' => Spring::Unk,
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    let groups = groups
+        .split(',')
+        .map(str::parse::<i64>)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((springs, groups))
+}
+
 fn parse_input(filename: &str) -> Result<Vec<(Vec<Spring>, Vec<i64>)>> {
     let input = std::fs::read_to_string(filename)?;
-    let ret = input
-        .lines()
-        .map(|line| {
-            let (springs, groups) = line.split_once(' ').unwrap();
-            let springs = springs
-                .chars()
-                .map(|c| match c {
-                    '.' => Spring::Ope,
-                    '#' => Spring::Dam,
-                    '?' => Spring::Unk,
-                    _ => unreachable!(),
-                })
-                .collect::<Vec<_>>();
-            let groups = groups
-                .split(',')
-                .map(str::parse::<i64>)
-                .collect::<Result<Vec<_>, _>>()?;
-            Ok((springs, groups))
+    input.lines().map(parse_line).collect()
+}
+
+pub fn springs_to_string(springs: &[Spring]) -> String {
+    springs
+        .iter()
+        .map(|s| match s {
+            Spring::Ope => '.',
+            Spring::Dam => '#',
+            Spring::Unk => '?',
         })
-        .collect();
-    ret
+        .collect()
 }
 
 fn count_springs<'a>(
@@ -70,40 +81,105 @@ fn count_springs<'a>(
     ret
 }
 
-fn unfold(springs: &[Spring], groups: &[i64]) -> (Vec<Spring>, Vec<i64>) {
-    let springs = springs
-        .iter()
-        .chain(Some(&Spring::Unk))
-        .chain(springs.iter())
-        .chain(Some(&Spring::Unk))
-        .chain(springs.iter())
-        .chain(Some(&Spring::Unk))
-        .chain(springs.iter())
-        .chain(Some(&Spring::Unk))
-        .chain(springs.iter())
-        .cloned()
-        .collect::<Vec<_>>();
-    let groups = groups
-        .iter()
-        .chain(groups.iter())
-        .chain(groups.iter())
-        .chain(groups.iter())
-        .chain(groups.iter())
-        .cloned()
-        .collect::<Vec<_>>();
+pub fn unfold(
+    springs: &[Spring],
+    groups: &[i64],
+    factor: usize,
+    separator: Spring,
+) -> (Vec<Spring>, Vec<i64>) {
+    let springs = vec![springs; factor].join(&separator);
+    let groups = groups.repeat(factor);
     (springs, groups)
 }
 
+fn group_end(springs: &[Spring], len: usize, p: usize) -> Option<usize> {
+    let end = p + len;
+    if end > springs.len()
+        || springs[p..end].contains(&Spring::Ope)
+        || springs.get(end) == Some(&Spring::Dam)
+    {
+        return None;
+    }
+    Some((end + 1).min(springs.len()))
+}
+
+fn build_table<T>(springs: &[Spring], groups: &[usize]) -> Vec<T>
+where
+    T: Clone + Zero + One + for<'a> AddAssign<&'a T>,
+{
+    let (n, m) = (springs.len(), groups.len());
+    let stride = m + 1;
+    let mut table = vec![T::zero(); (n + 1) * stride];
+    let mut clean = true;
+    for p in (0..=n).rev() {
+        clean &= springs.get(p) != Some(&Spring::Dam);
+        if clean {
+            table[p * stride + m] = T::one();
+        }
+        for g in (0..m).rev() {
+            let mut ways = T::zero();
+            if let Some(Spring::Ope | Spring::Unk) = springs.get(p) {
+                ways += &table[(p + 1) * stride + g];
+            }
+            if let Some(next) = group_end(springs, groups[g], p) {
+                ways += &table[next * stride + g + 1];
+            }
+            table[p * stride + g] = ways;
+        }
+    }
+    table
+}
+
+pub fn count_arrangements<T>(springs: &[Spring], groups: &[i64]) -> T
+where
+    T: Clone + Zero + One + for<'a> AddAssign<&'a T>,
+{
+    let groups: Vec<usize> = groups.iter().map(|&g| g as usize).collect();
+    build_table::<T>(springs, &groups).swap_remove(0)
+}
+
+pub fn total_arrangements<T>(filename: &str, factor: usize, separator: Spring) -> Result<T>
+where
+    T: Clone + Zero + One + for<'a> AddAssign<&'a T>,
+{
+    let input = parse_input(filename)?;
+    let mut ret = T::zero();
+    for (springs, groups) in input.iter() {
+        let (springs, groups) = unfold(springs, groups, factor, separator);
+        ret += &count_arrangements::<T>(&springs, &groups);
+    }
+    Ok(ret)
+}
+
+#[test_case("???.### 1,1,3", 50 => matches Ok(v) if v == BigUint::from(1u8))]
+#[test_case(".??..??...?##. 1,1,3", 5 => matches Ok(v) if v == BigUint::from(16384u32))]
+#[test_case(".??..??...?##. 1,1,3", 50 => matches Ok(v) if v == BigUint::from(2u8).pow(149))]
+pub fn count_unfolded(line: &str, factor: usize) -> Result<BigUint> {
+    let (springs, groups) = parse_line(line)?;
+    let (springs, groups) = unfold(&springs, &groups, factor, Spring::Unk);
+    Ok(count_arrangements(&springs, &groups))
+}
+
 #[test_case("inputs/example-12-1.txt", false => matches Ok(21))]
 #[test_case("inputs/input-12.txt", false => matches Ok(7916))]
 #[test_case("inputs/example-12-1.txt", true => matches Ok(525152))]
 #[test_case("inputs/input-12.txt", true => matches Ok(37366887898686))]
 pub fn puzzle1and2(filename: &str, unfolded: bool) -> Result<i64> {
+    let factor = if unfolded { 5 } else { 1 };
+    let ret = total_arrangements::<u128>(filename, factor, Spring::Unk)?;
+    Ok(ret.try_into()?)
+}
+
+#[test_case("inputs/example-12-1.txt", false => matches Ok(21))]
+#[test_case("inputs/input-12.txt", false => matches Ok(7916))]
+#[test_case("inputs/example-12-1.txt", true => matches Ok(525152))]
+#[test_case("inputs/input-12.txt", true => matches Ok(37366887898686))]
+pub fn puzzle1and2_recursive(filename: &str, unfolded: bool) -> Result<i64> {
     let mut input = parse_input(filename)?;
     if unfolded {
         input = input
             .iter()
-            .map(|(springs, groups)| unfold(springs, groups))
+            .map(|(springs, groups)| unfold(springs, groups, 5, Spring::Unk))
             .collect();
     }
     let mut ret = 0;
@@ -114,3 +190,244 @@ pub fn puzzle1and2(filename: &str, unfolded: bool) -> Result<i64> {
     }
     Ok(ret)
 }
+
+pub struct SpringRow {
+    springs: Vec<Spring>,
+    groups: Vec<usize>,
+    table: Vec<BigUint>,
+}
+
+fn random_below(rng: &mut fastrand::Rng, n: &BigUint) -> BigUint {
+    let bits = n.bits() as usize;
+    let digits = bits.div_ceil(32);
+    loop {
+        let words: Vec<u32> = (0..digits).map(|_| rng.u32(..)).collect();
+        let ret = BigUint::from_slice(&words) >> (digits * 32 - bits);
+        if &ret < n {
+            return ret;
+        }
+    }
+}
+
+impl SpringRow {
+    pub fn new(springs: &[Spring], groups: &[i64]) -> Self {
+        let springs = springs.to_vec();
+        let groups: Vec<usize> = groups.iter().map(|&g| g as usize).collect();
+        let table = build_table(&springs, &groups);
+        SpringRow {
+            springs,
+            groups,
+            table,
+        }
+    }
+
+    fn ways(&self, p: usize, g: usize) -> &BigUint {
+        &self.table[p * (self.groups.len() + 1) + g]
+    }
+
+    fn place(&self, p: usize, g: usize) -> Option<usize> {
+        group_end(&self.springs, self.groups[g], p)
+    }
+
+    fn options(&self, p: usize, g: usize) -> (BigUint, BigUint) {
+        let dot = match self.springs.get(p) {
+            Some(Spring::Ope | Spring::Unk) => self.ways(p + 1, g).clone(),
+            _ => BigUint::zero(),
+        };
+        let group = match self.place(p, g) {
+            Some(next) => self.ways(next, g + 1).clone(),
+            None => BigUint::zero(),
+        };
+        (dot, group)
+    }
+
+    pub fn count(&self) -> BigUint {
+        self.ways(0, 0).clone()
+    }
+
+    pub fn arrangements(&self) -> Arrangements<'_> {
+        let stack = if self.ways(0, 0).is_zero() {
+            vec![]
+        } else {
+            vec![(0, 0, 0)]
+        };
+        Arrangements {
+            row: self,
+            stack,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn sample(&self, rng: &mut fastrand::Rng) -> Option<Vec<Spring>> {
+        if self.ways(0, 0).is_zero() {
+            return None;
+        }
+        let mut ret = Vec::with_capacity(self.springs.len());
+        let (mut p, mut g) = (0, 0);
+        while g < self.groups.len() {
+            let (dot, group) = self.options(p, g);
+            if random_below(rng, &(dot + &group)) < group {
+                let next = self.place(p, g).unwrap();
+                ret.extend(std::iter::repeat_n(Spring::Dam, self.groups[g]));
+                ret.extend(std::iter::repeat_n(Spring::Ope, next - p - self.groups[g]));
+                (p, g) = (next, g + 1);
+            } else {
+                ret.push(Spring::Ope);
+                p += 1;
+            }
+        }
+        ret.resize(self.springs.len(), Spring::Ope);
+        Some(ret)
+    }
+
+    pub fn damage_counts(&self) -> Vec<BigUint> {
+        let (n, m) = (self.springs.len(), self.groups.len());
+        let mut prefix = vec![vec![BigUint::zero(); m + 1]; n + 1];
+        prefix[0][0] = BigUint::one();
+        let mut starts = vec![BigUint::zero(); n + 1];
+        let mut ends = vec![BigUint::zero(); n + 1];
+        for p in 0..=n {
+            for g in 0..=m {
+                let ways = std::mem::take(&mut prefix[p][g]);
+                if ways.is_zero() {
+                    continue;
+                }
+                if let Some(Spring::Ope | Spring::Unk) = self.springs.get(p) {
+                    prefix[p + 1][g] += &ways;
+                }
+                if g < m {
+                    if let Some(next) = self.place(p, g) {
+                        prefix[next][g + 1] += &ways;
+                        let through = &ways * self.ways(next, g + 1);
+                        starts[p] += &through;
+                        ends[p + self.groups[g]] += through;
+                    }
+                }
+            }
+        }
+        let mut acc = BigUint::zero();
+        (0..n)
+            .map(|p| {
+                acc += &starts[p];
+                acc -= &ends[p];
+                acc.clone()
+            })
+            .collect()
+    }
+
+    pub fn damage_probabilities(&self) -> Option<Vec<f64>> {
+        if self.ways(0, 0).is_zero() {
+            return None;
+        }
+        let total = self.ways(0, 0).to_f64().unwrap_or(f64::INFINITY);
+        let ret = self
+            .damage_counts()
+            .into_iter()
+            .map(|d| d.to_f64().unwrap_or(f64::INFINITY) / total)
+            .collect();
+        Some(ret)
+    }
+}
+
+pub struct Arrangements<'a> {
+    row: &'a SpringRow,
+    stack: Vec<(usize, usize, u8)>,
+    buf: Vec<Spring>,
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = Vec<Spring>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.row;
+        while let Some((p, g, stage)) = self.stack.last_mut() {
+            let (p, g) = (*p, *g);
+            self.buf.truncate(p);
+            if g == row.groups.len() {
+                self.stack.pop();
+                self.buf.resize(row.springs.len(), Spring::Ope);
+                return Some(self.buf.clone());
+            }
+            *stage += 1;
+            match *stage {
+                1 => {
+                    if let Some(next) = row.place(p, g) {
+                        if !row.ways(next, g + 1).is_zero() {
+                            self.buf
+                                .extend(std::iter::repeat_n(Spring::Dam, row.groups[g]));
+                            self.buf.resize(next, Spring::Ope);
+                            self.stack.push((next, g + 1, 0));
+                        }
+                    }
+                }
+                2 => {
+                    if let Some(Spring::Ope | Spring::Unk) = row.springs.get(p) {
+                        if !row.ways(p + 1, g).is_zero() {
+                            self.buf.push(Spring::Ope);
+                            self.stack.push((p + 1, g, 0));
+                        }
+                    }
+                }
+                _ => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+pub fn spring_rows(filename: &str, unfolded: bool) -> Result<Vec<SpringRow>> {
+    let input = parse_input(filename)?;
+    let ret = input
+        .iter()
+        .map(|(springs, groups)| {
+            let factor = if unfolded { 5 } else { 1 };
+            let (springs, groups) = unfold(springs, groups, factor, Spring::Unk);
+            SpringRow::new(&springs, &groups)
+        })
+        .collect();
+    Ok(ret)
+}
+
+#[test_case("???.### 1,1,3" => matches Ok(v) if v == ["#.#.###"])]
+#[test_case(".??..??...?##. 1,1,3" => matches Ok(v) if v == [".#...#....###.", ".#....#...###.", "..#..#....###.", "..#...#...###."])]
+#[test_case("?###???????? 3,2,1" => matches Ok(v) if v.len() == 10)]
+#[test_case("#.# 2" => matches Ok(v) if v.is_empty())]
+pub fn list_arrangements(line: &str) -> Result<Vec<String>> {
+    let (springs, groups) = parse_line(line)?;
+    let row = SpringRow::new(&springs, &groups);
+    Ok(row.arrangements().map(|a| springs_to_string(&a)).collect())
+}
+
+#[test_case("???.### 1,1,3" => matches Ok(Some(v)) if v == [1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0])]
+#[test_case("???? 1" => matches Ok(Some(v)) if v == [0.25, 0.25, 0.25, 0.25])]
+#[test_case("???? 1,1" => matches Ok(Some(v)) if v == [2.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0])]
+#[test_case("#.# 2" => matches Ok(None))]
+pub fn damage_probabilities(line: &str) -> Result<Option<Vec<f64>>> {
+    let (springs, groups) = parse_line(line)?;
+    Ok(SpringRow::new(&springs, &groups).damage_probabilities())
+}
+
+#[test_case("???.### 1,1,3", 1, 20 => matches Ok(1))]
+#[test_case(".??..??...?##. 1,1,3", 2, 200 => matches Ok(4))]
+#[test_case("?###???????? 3,2,1", 3, 500 => matches Ok(10))]
+#[test_case("#.# 2", 4, 20 => matches Ok(0))]
+pub fn sampled_arrangements(line: &str, seed: u64, draws: usize) -> Result<usize> {
+    let (springs, groups) = parse_line(line)?;
+    let row = SpringRow::new(&springs, &groups);
+    let valid: HashSet<String> = row.arrangements().map(|a| springs_to_string(&a)).collect();
+    let mut rng = fastrand::Rng::with_seed(seed);
+    let mut seen = HashSet::new();
+    for _ in 0..draws {
+        let Some(sample) = row.sample(&mut rng) else {
+            break;
+        };
+        let sample = springs_to_string(&sample);
+        if !valid.contains(&sample) {
+            anyhow::bail!("Sampled invalid arrangement {}", sample);
+        }
+        seen.insert(sample);
+    }
+    Ok(seen.len())
+}
@@ -1,94 +1,132 @@
 use anyhow::Result;
-use pathfinding::matrix::Matrix;
 use test_case::test_case;
 
-fn parse_input(filename: &str) -> Result<Vec<Matrix<bool>>> {
-    let input = std::fs::read_to_string(filename)?;
-    let ret = input
-        .split("\n\n")
-        .map(|m| {
-            Matrix::from_rows(m.lines().map(|l| {
-                l.chars().map(|c| match c {
-                    '.' => false,
-                    '#' => true,
-                    _ => unreachable!(),
-                })
-            }))
-            .unwrap()
-        })
-        .collect();
-    Ok(ret)
+type Line = Vec<u64>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    rows: Vec<Line>,
+    cols: Vec<Line>,
+}
+
+fn set_bit(line: &mut Line, i: usize) {
+    line[i / 64] |= 1 << (i % 64);
 }
 
-fn find_mirror(input: &Matrix<bool>) -> Option<usize> {
-    for y in 1..input.rows {
-        for diff in 1..input.rows {
-            if diff > y || y + diff > input.rows {
-                if diff > 0 {
-                    return Some(y);
+fn parse_pattern(m: &str) -> Result<Pattern> {
+    let lines: Vec<&[u8]> = m.lines().map(str::as_bytes).collect();
+    let width = lines.first().map_or(0, |l| l.len());
+    let mut rows = vec![vec![0u64; width.div_ceil(64)]; lines.len()];
+    let mut cols = vec![vec![0u64; lines.len().div_ceil(64)]; width];
+    for (y, line) in lines.iter().enumerate() {
+        if line.len() != width {
+            return Err(anyhow::anyhow!(
+                "Row {} has length {} instead of {}",
+                y,
+                line.len(),
+                width
+            ));
+        }
+        for (x, &c) in line.iter().enumerate() {
+            match c {
+                b'.' => (),
+                b'#' => {
+                    set_bit(&mut rows[y], x);
+                    set_bit(&mut cols[x], y);
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid character {:?} at ({}, {})",
+                        c as char,
+                        x,
+                        y
+                    ))
                 }
-                break;
-            }
-            if (0..input.columns).any(|x| input[(y + diff - 1, x)] != input[(y - diff, x)]) {
-                break;
             }
         }
     }
-    None
+    Ok(Pattern { rows, cols })
 }
 
-#[test_case("inputs/example-13-1.txt" => matches Ok(405))]
-#[test_case("inputs/input-13.txt" => matches Ok(33356))]
-pub fn puzzle1(filename: &str) -> Result<i64> {
+fn parse_input(filename: &str) -> Result<Vec<Pattern>> {
+    let input = std::fs::read_to_string(filename)?;
+    input.split("\n\n").map(parse_pattern).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal(usize),
+    Vertical(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reflection {
+    pub axis: Axis,
+    pub mismatches: u32,
+}
+
+fn find_axes(lines: &[Line], k: u32) -> impl Iterator<Item = (usize, u32)> + '_ {
+    (1..lines.len()).filter_map(move |y| {
+        let mut mismatches = 0;
+        for (a, b) in lines[..y].iter().rev().zip(&lines[y..]) {
+            mismatches += a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum::<u32>();
+            if mismatches > k {
+                return None;
+            }
+        }
+        Some((y, mismatches))
+    })
+}
+
+pub fn find_reflections(pattern: &Pattern, k: u32) -> Vec<Reflection> {
+    let horizontal = find_axes(&pattern.rows, k).map(|(y, mismatches)| Reflection {
+        axis: Axis::Horizontal(y),
+        mismatches,
+    });
+    let vertical = find_axes(&pattern.cols, k).map(|(x, mismatches)| Reflection {
+        axis: Axis::Vertical(x),
+        mismatches,
+    });
+    horizontal.chain(vertical).collect()
+}
+
+#[test_case("inputs/example-13-1.txt", 0 => matches Ok(v) if v[0] == [Reflection { axis: Axis::Vertical(5), mismatches: 0 }])]
+#[test_case("inputs/example-13-1.txt", 1 => matches Ok(v) if v[0] == [Reflection { axis: Axis::Horizontal(3), mismatches: 1 }, Reflection { axis: Axis::Vertical(5), mismatches: 0 }])]
+#[test_case("inputs/example-13-2.txt", 0 => matches Ok(v) if v[0] == [Reflection { axis: Axis::Vertical(35), mismatches: 0 }])]
+#[test_case("inputs/example-13-3.txt", 0 => matches Err(_))]
+#[test_case("inputs/example-13-4.txt", 0 => matches Err(_))]
+pub fn reflections(filename: &str, k: u32) -> Result<Vec<Vec<Reflection>>> {
+    let input = parse_input(filename)?;
+    Ok(input.iter().map(|m| find_reflections(m, k)).collect())
+}
+
+pub fn summarize(filename: &str, smudges: u32) -> Result<i64> {
     let input = parse_input(filename)?;
     let mut total = 0;
     for m in input {
-        let count =
-            (find_mirror(&m).unwrap_or(0) * 100) + find_mirror(&m.rotated_cw(1)).unwrap_or(0);
-        total += count as i64;
+        for r in find_reflections(&m, smudges) {
+            total += match r.axis {
+                _ if r.mismatches != smudges => 0,
+                Axis::Horizontal(y) => y as i64 * 100,
+                Axis::Vertical(x) => x as i64,
+            };
+        }
     }
     Ok(total)
 }
 
-fn find_mirror_smudged(input: &Matrix<bool>) -> Option<usize> {
-    for y in 1..input.rows {
-        let mut smudged = false;
-        for diff in 1..input.rows {
-            if diff > y || y + diff > input.rows {
-                if diff > 0 && smudged {
-                    return Some(y);
-                }
-                break;
-            }
-            match (0..input.columns)
-                .filter(|x| input[(y + diff - 1, *x)] != input[(y - diff, *x)])
-                .count()
-            {
-                0 => (),
-                1 if smudged => {
-                    break;
-                }
-                1 => {
-                    smudged = true;
-                }
-                _ => {
-                    break;
-                }
-            }
-        }
-    }
-    None
+#[test_case("inputs/example-13-1.txt" => matches Ok(405))]
+#[test_case("inputs/input-13.txt" => matches Ok(33356))]
+pub fn puzzle1(filename: &str) -> Result<i64> {
+    summarize(filename, 0)
 }
 
 #[test_case("inputs/example-13-1.txt" => matches Ok(400))]
 #[test_case("inputs/input-13.txt" => matches Ok(28475))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let mut total = 0;
-    for m in input {
-        let count = (find_mirror_smudged(&m).unwrap_or(0) * 100)
-            + find_mirror_smudged(&m.rotated_cw(1)).unwrap_or(0);
-        total += count as i64;
-    }
-    Ok(total)
+    summarize(filename, 1)
 }
@@ -1,120 +1,300 @@
+use crate::cycle::{find_cycle, state_at, Mode};
 use anyhow::Result;
-use pathfinding::matrix::Matrix;
 use test_case::test_case;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Rock {
-    R,
-    C,
-    E,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
 }
 
-fn parse_input(filename: &str) -> Result<Matrix<Rock>> {
-    let input = std::fs::read_to_string(filename)?;
-    let ret = Matrix::from_rows(input.lines().filter(|l| !l.is_empty()).map(|l| {
-        l.chars().map(|c| match c {
-            'O' => Rock::R,
-            '#' => Rock::C,
-            '.' => Rock::E,
-            _ => unreachable!(),
+/// Rows of bits, each `stride` words long.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct Bitboard {
+    stride: usize,
+    words: Vec<u64>,
+}
+
+impl Bitboard {
+    fn new(lines: usize, len: usize) -> Self {
+        let stride = len.div_ceil(64);
+        Bitboard {
+            stride,
+            words: vec![0; lines * stride],
+        }
+    }
+
+    fn line(&self, i: usize) -> &[u64] {
+        &self.words[i * self.stride..(i + 1) * self.stride]
+    }
+
+    fn lines_mut(&mut self) -> impl Iterator<Item = &mut [u64]> {
+        self.words.chunks_mut(self.stride.max(1))
+    }
+
+    fn get(&self, i: usize, x: usize) -> bool {
+        self.words[i * self.stride + x / 64] & (1 << (x % 64)) != 0
+    }
+
+    fn set(&mut self, i: usize, x: usize) {
+        self.words[i * self.stride + x / 64] |= 1 << (x % 64);
+    }
+
+    fn ones(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.words.iter().enumerate().flat_map(move |(w, &word)| {
+            let (i, base) = (w / self.stride, w % self.stride * 64);
+            std::iter::successors(Some(word).filter(|&b| b != 0), |&b| {
+                Some(b & (b - 1)).filter(|&b| b != 0)
+            })
+            .map(move |b| (i, base + b.trailing_zeros() as usize))
         })
-    }))?;
-    Ok(ret)
-}
-
-#[allow(dead_code)]
-fn print_matrix(matrix: &Matrix<Rock>) {
-    for y in 0..matrix.rows {
-        for x in 0..matrix.columns {
-            print!(
-                "{}",
-                match matrix[(y, x)] {
-                    Rock::R => 'O',
-                    Rock::C => '#',
-                    Rock::E => '.',
-                }
-            );
+    }
+
+    fn transpose_into(&self, out: &mut Bitboard) {
+        out.words.fill(0);
+        for (i, x) in self.ones() {
+            out.set(x, i);
         }
-        println!();
     }
 }
 
-fn roll(matrix: &mut Matrix<Rock>) {
-    for x in 0..matrix.columns {
-        let mut free_row = None;
-        for y in 0..matrix.rows {
-            match matrix[(y, x)] {
-                Rock::R => {
-                    if let Some(r) = free_row {
-                        matrix[(y, x)] = Rock::E;
-                        matrix[(r, x)] = Rock::R;
-                        free_row = Some(r + 1);
-                    }
-                }
-                Rock::C => {
-                    free_row = None;
-                }
-                Rock::E => {
-                    if free_row.is_none() {
-                        free_row = Some(y);
-                    }
+/// The words of a line covering bits `lo..hi`, with the mask of those bits.
+fn range_words(lo: usize, hi: usize) -> impl Iterator<Item = (usize, u64)> {
+    (lo / 64..hi.div_ceil(64)).map(move |w| {
+        let start = lo.max(w * 64) - w * 64;
+        let end = hi.min(w * 64 + 64) - w * 64;
+        let bits = if end - start == 64 {
+            u64::MAX
+        } else {
+            ((1 << (end - start)) - 1) << start
+        };
+        (w, bits)
+    })
+}
+
+fn count_range(line: &[u64], lo: usize, hi: usize) -> usize {
+    range_words(lo, hi)
+        .map(|(w, bits)| (line[w] & bits).count_ones() as usize)
+        .sum()
+}
+
+fn fill_range(line: &mut [u64], lo: usize, hi: usize, on: bool) {
+    for (w, bits) in range_words(lo, hi) {
+        line[w] = if on { line[w] | bits } else { line[w] & !bits };
+    }
+}
+
+fn segments(walls: &[u64], len: usize) -> Vec<(usize, usize)> {
+    let mut ret = Vec::new();
+    let mut lo = 0;
+    for x in 0..=len {
+        if x == len || walls[x / 64] & (1 << (x % 64)) != 0 {
+            if x > lo {
+                ret.push((lo, x));
+            }
+            lo = x + 1;
+        }
+    }
+    ret
+}
+
+fn pack(lines: &mut Bitboard, segments: &[Vec<(usize, usize)>], toward_low: bool) {
+    for (line, segs) in lines.lines_mut().zip(segments) {
+        for &(lo, hi) in segs {
+            let count = count_range(line, lo, hi);
+            fill_range(line, lo, hi, false);
+            if toward_low {
+                fill_range(line, lo, lo + count, true);
+            } else {
+                fill_range(line, hi - count, hi, true);
+            }
+        }
+    }
+}
+
+/// The rounded rocks, kept both row by row and column by column so that
+/// every tilt packs lines of whichever board runs along its direction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct Rocks {
+    rows: Bitboard,
+    columns: Bitboard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    rows: usize,
+    columns: usize,
+    rocks: Rocks,
+    cubes: Bitboard,
+    row_segments: Vec<Vec<(usize, usize)>>,
+    column_segments: Vec<Vec<(usize, usize)>>,
+}
+
+impl Platform {
+    fn parse(input: &str) -> Result<Platform> {
+        let lines: Vec<&str> = input.lines().filter(|l| !l.is_empty()).collect();
+        let rows = lines.len();
+        let columns = lines.first().map_or(0, |l| l.len());
+        let mut rocks = Bitboard::new(rows, columns);
+        let mut cubes = Bitboard::new(rows, columns);
+        for (y, line) in lines.iter().enumerate() {
+            if line.len() != columns {
+                anyhow::bail!("Row {} has length {} instead of {}", y, line.len(), columns);
+            }
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    'O' => rocks.set(y, x),
+                    '#' => cubes.set(y, x),
+                    '.' => (),
+                    _ => anyhow::bail!("Invalid character {:?} at ({}, {})", c, y, x),
                 }
             }
         }
+        let row_segments = (0..rows)
+            .map(|y| segments(cubes.line(y), columns))
+            .collect();
+        let mut column_cubes = Bitboard::new(columns, rows);
+        cubes.transpose_into(&mut column_cubes);
+        let column_segments = (0..columns)
+            .map(|x| segments(column_cubes.line(x), rows))
+            .collect();
+        let mut column_rocks = Bitboard::new(columns, rows);
+        rocks.transpose_into(&mut column_rocks);
+        Ok(Platform {
+            rows,
+            columns,
+            rocks: Rocks {
+                rows: rocks,
+                columns: column_rocks,
+            },
+            cubes,
+            row_segments,
+            column_segments,
+        })
+    }
+
+    fn tilt_rocks(&self, rocks: &mut Rocks, dir: Direction) {
+        match dir {
+            Direction::West | Direction::East => {
+                pack(&mut rocks.rows, &self.row_segments, dir == Direction::West);
+                rocks.rows.transpose_into(&mut rocks.columns);
+            }
+            Direction::North | Direction::South => {
+                pack(
+                    &mut rocks.columns,
+                    &self.column_segments,
+                    dir == Direction::North,
+                );
+                rocks.columns.transpose_into(&mut rocks.rows);
+            }
+        }
+    }
+
+    fn spin_rocks(&self, rocks: &mut Rocks) {
+        for dir in [
+            Direction::North,
+            Direction::West,
+            Direction::South,
+            Direction::East,
+        ] {
+            self.tilt_rocks(rocks, dir);
+        }
+    }
+
+    pub fn tilt(&mut self, dir: Direction) {
+        let mut rocks = std::mem::take(&mut self.rocks);
+        self.tilt_rocks(&mut rocks, dir);
+        self.rocks = rocks;
+    }
+
+    pub fn spin(&mut self) {
+        let mut rocks = std::mem::take(&mut self.rocks);
+        self.spin_rocks(&mut rocks);
+        self.rocks = rocks;
+    }
+
+    pub fn load(&self, dir: Direction) -> u64 {
+        self.rocks
+            .rows
+            .ones()
+            .map(|(y, x)| match dir {
+                Direction::North => self.rows - y,
+                Direction::South => y + 1,
+                Direction::East => x + 1,
+                Direction::West => self.columns - x,
+            } as u64)
+            .sum()
+    }
+
+    pub fn after_spins(&self, spins: usize) -> Platform {
+        let step = |rocks: &Rocks| {
+            let mut rocks = rocks.clone();
+            self.spin_rocks(&mut rocks);
+            rocks
+        };
+        let period = find_cycle(&self.rocks, step, Mode::Hashed);
+        Platform {
+            rocks: state_at(&self.rocks, step, &period, spins),
+            ..self.clone()
+        }
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for y in 0..self.rows {
+            for x in 0..self.columns {
+                let c = if self.rocks.rows.get(y, x) {
+                    'O'
+                } else if self.cubes.get(y, x) {
+                    '#'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
 
+fn parse_input(filename: &str) -> Result<Platform> {
+    let input = std::fs::read_to_string(filename)?;
+    Platform::parse(&input)
+}
+
+#[test_case("inputs/example-14-1.txt", Direction::North => matches Ok(136))]
+#[test_case("inputs/example-14-1.txt", Direction::East => matches Ok(105))]
+#[test_case("inputs/example-14-1.txt", Direction::South => matches Ok(132))]
+#[test_case("inputs/example-14-1.txt", Direction::West => matches Ok(147))]
+#[test_case("inputs/example-14-2.txt", Direction::North => matches Ok(476558))]
+#[test_case("inputs/example-14-3.txt", Direction::North => matches Err(_))]
+pub fn tilted_load(filename: &str, dir: Direction) -> Result<u64> {
+    let mut input = parse_input(filename)?;
+    input.tilt(dir);
+    Ok(input.load(dir))
+}
+
+#[test_case("inputs/example-14-1.txt", 1 => matches Ok(87))]
+#[test_case("inputs/example-14-1.txt", 3 => matches Ok(69))]
+#[test_case("inputs/example-14-1.txt", 1000000000 => matches Ok(64))]
+#[test_case("inputs/example-14-2.txt", 3 => matches Ok(449765))]
+pub fn load_after_spins(filename: &str, spins: usize) -> Result<u64> {
+    let input = parse_input(filename)?;
+    Ok(input.after_spins(spins).load(Direction::North))
+}
+
 #[test_case("inputs/example-14-1.txt" => matches Ok(136))]
 #[test_case("inputs/input-14.txt" => matches Ok(108759))]
 pub fn puzzle1(filename: &str) -> Result<i64> {
-    let mut input = parse_input(filename)?;
-    roll(&mut input);
-    let ret = input
-        .items()
-        .filter(|(_, r)| **r == Rock::R)
-        .map(|((y, _), _)| input.rows - y)
-        .sum::<usize>() as i64;
-    Ok(ret)
-}
-
-fn cycle(matrix: &mut Matrix<Rock>) {
-    roll(matrix);
-    matrix.rotate_cw(1);
-    roll(matrix);
-    matrix.rotate_cw(1);
-    roll(matrix);
-    matrix.rotate_cw(1);
-    roll(matrix);
-    matrix.rotate_cw(1);
-}
-
-fn find_loop(matrix: &mut Matrix<Rock>) -> (usize, usize) {
-    let mut seen = std::collections::HashMap::new();
-    for num in 1.. {
-        cycle(matrix);
-        if seen.contains_key(matrix) {
-            return (seen[matrix], num);
-        } else {
-            seen.insert(matrix.clone(), num);
-        }
-    }
-    unreachable!();
+    Ok(tilted_load(filename, Direction::North)? as i64)
 }
 
 #[test_case("inputs/example-14-1.txt" => matches Ok(64))]
 #[test_case("inputs/input-14.txt" => matches Ok(89089))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
-    let mut input = parse_input(filename)?;
-    let (start, end) = find_loop(&mut input);
-    let rem = (1000000000 - end) % (end - start);
-    //println!("start: {}, end: {}, rem: {}", start, end, rem);
-    for _ in 0..rem {
-        cycle(&mut input);
-    }
-    let ret = input
-        .items()
-        .filter(|(_, r)| **r == Rock::R)
-        .map(|((y, _), _)| input.rows - y)
-        .sum::<usize>() as i64;
-    Ok(ret)
+    Ok(load_after_spins(filename, 1000000000)? as i64)
 }
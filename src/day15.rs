@@ -1,4 +1,5 @@
 use anyhow::Result;
+use std::collections::HashMap;
 use test_case::test_case;
 
 fn calc_hash(s: &[u8]) -> u8 {
@@ -14,68 +15,302 @@ pub fn puzzle1(filename: &str) -> Result<i64> {
     Ok(ret)
 }
 
-#[derive(Debug)]
-enum Instr<'a> {
-    Rem(u8, &'a [u8]),
-    Set(u8, &'a [u8], u8),
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    Rem(Vec<u8>),
+    Set(Vec<u8>, u8),
 }
 
-fn parse_instr(part: &str) -> Instr {
-    if part.ends_with('-') {
-        let part = &part.as_bytes()[..part.len() - 1];
-        Instr::Rem(calc_hash(part), part)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstrError {
+    EmptyLabel(usize),
+    MissingOperation(usize),
+    MissingFocalLength(usize),
+    InvalidFocalLength(usize),
+}
+
+impl std::fmt::Display for InstrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstrError::EmptyLabel(i) => write!(f, "Instruction {} has an empty label", i),
+            InstrError::MissingOperation(i) => write!(f, "Instruction {} has no operation", i),
+            InstrError::MissingFocalLength(i) => {
+                write!(f, "Instruction {} is missing a focal length", i)
+            }
+            InstrError::InvalidFocalLength(i) => {
+                write!(f, "Instruction {} has an invalid focal length", i)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InstrError {}
+
+fn parse_instr(index: usize, part: &str) -> Result<Instr, InstrError> {
+    let instr = if let Some(label) = part.strip_suffix('-') {
+        Instr::Rem(label.as_bytes().to_vec())
+    } else if let Some((label, lens)) = part.split_once('=') {
+        if lens.is_empty() {
+            return Err(InstrError::MissingFocalLength(index));
+        }
+        let lens = lens
+            .parse()
+            .map_err(|_| InstrError::InvalidFocalLength(index))?;
+        Instr::Set(label.as_bytes().to_vec(), lens)
+    } else if part.is_empty() {
+        return Err(InstrError::EmptyLabel(index));
     } else {
-        let (part, lens) = part.split_once('=').unwrap();
-        let part = part.as_bytes();
-        let lens = lens.parse().unwrap();
-        Instr::Set(calc_hash(part), part, lens)
+        return Err(InstrError::MissingOperation(index));
+    };
+    match &instr {
+        Instr::Rem(label) | Instr::Set(label, _) if label.is_empty() => {
+            Err(InstrError::EmptyLabel(index))
+        }
+        _ => Ok(instr),
     }
 }
 
-#[allow(dead_code)]
-fn print_boxes(boxes: &[Vec<(&[u8], u8)>]) {
-    for (i, box_) in boxes.iter().enumerate() {
-        if !box_.is_empty() {
-            println!("{}: {:?}", i, box_);
+pub fn parse_sequence(input: &str) -> Result<Vec<Instr>, InstrError> {
+    input
+        .trim()
+        .split(',')
+        .enumerate()
+        .map(|(i, part)| parse_instr(i, part))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added(usize, Vec<u8>, u8),
+    Replaced(usize, Vec<u8>, u8, u8),
+    Removed(usize, Vec<u8>, u8),
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Default)]
+struct LensBox {
+    slots: Vec<Option<(Vec<u8>, u8)>>,
+    index: HashMap<Vec<u8>, usize>,
+}
+
+impl LensBox {
+    fn compact(&mut self) {
+        self.slots.retain(Option::is_some);
+        for (i, (label, _)) in self.slots.iter().flatten().enumerate() {
+            *self.index.get_mut(label).unwrap() = i;
         }
     }
+
+    fn iter(&self) -> impl Iterator<Item = (&[u8], u8)> {
+        self.slots.iter().flatten().map(|(l, f)| (l.as_slice(), *f))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LensBoxes {
+    boxes: Vec<LensBox>,
+    hasher: fn(&[u8]) -> usize,
+}
+
+impl Default for LensBoxes {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-fn calc_power(boxes: &[Vec<(&[u8], u8)>]) -> i64 {
-    let mut ret = 0;
-    for (i, box_) in boxes.iter().enumerate() {
-        for (j, (_, lens)) in box_.iter().enumerate() {
-            ret += (i as i64 + 1) * (j as i64 + 1) * *lens as i64;
+impl LensBoxes {
+    pub fn new() -> Self {
+        Self::with_hasher(256, |s| calc_hash(s) as usize)
+    }
+
+    pub fn with_hasher(buckets: usize, hasher: fn(&[u8]) -> usize) -> Self {
+        assert!(buckets > 0, "LensBoxes needs at least one bucket");
+        LensBoxes {
+            boxes: vec![LensBox::default(); buckets],
+            hasher,
+        }
+    }
+
+    pub fn buckets(&self) -> usize {
+        self.boxes.len()
+    }
+
+    pub fn box_of(&self, label: &[u8]) -> usize {
+        (self.hasher)(label) % self.boxes.len()
+    }
+
+    pub fn get(&self, label: &[u8]) -> Option<u8> {
+        let box_ = &self.boxes[self.box_of(label)];
+        let &slot = box_.index.get(label)?;
+        box_.slots[slot].as_ref().map(|&(_, f)| f)
+    }
+
+    pub fn insert(&mut self, label: &[u8], focal: u8) -> Option<u8> {
+        let i = self.box_of(label);
+        let box_ = &mut self.boxes[i];
+        if let Some(&slot) = box_.index.get(label) {
+            let (_, f) = box_.slots[slot].as_mut().unwrap();
+            return Some(std::mem::replace(f, focal));
+        }
+        box_.index.insert(label.to_vec(), box_.slots.len());
+        box_.slots.push(Some((label.to_vec(), focal)));
+        None
+    }
+
+    pub fn remove(&mut self, label: &[u8]) -> Option<u8> {
+        let i = self.box_of(label);
+        let box_ = &mut self.boxes[i];
+        let slot = box_.index.remove(label)?;
+        let (_, focal) = box_.slots[slot].take().unwrap();
+        if box_.slots.len() > 2 * box_.index.len() + 8 {
+            box_.compact();
+        }
+        Some(focal)
+    }
+
+    pub fn lenses(&self, i: usize) -> impl Iterator<Item = (&[u8], u8)> {
+        self.boxes[i].iter()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &[u8], u8)> {
+        self.boxes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, b)| b.iter().map(move |(l, f)| (i, l, f)))
+    }
+
+    pub fn focusing_power(&self) -> i64 {
+        let mut ret = 0;
+        for (i, box_) in self.boxes.iter().enumerate() {
+            for (j, (_, lens)) in box_.iter().enumerate() {
+                ret += (i as i64 + 1) * (j as i64 + 1) * lens as i64;
+            }
+        }
+        ret
+    }
+
+    pub fn apply(&mut self, instr: &Instr) -> Change {
+        match instr {
+            Instr::Rem(label) => match self.remove(label) {
+                Some(focal) => Change::Removed(self.box_of(label), label.clone(), focal),
+                None => Change::Unchanged,
+            },
+            Instr::Set(label, focal) => match self.insert(label, *focal) {
+                Some(old) if old == *focal => Change::Unchanged,
+                Some(old) => Change::Replaced(self.box_of(label), label.clone(), old, *focal),
+                None => Change::Added(self.box_of(label), label.clone(), *focal),
+            },
         }
     }
-    ret
 }
 
-#[test_case("inputs/example-15-1.txt" => matches Ok(145))]
-#[test_case("inputs/input-15.txt" => matches Ok(200277))]
-pub fn puzzle2(filename: &str) -> Result<i64> {
-    let input = std::fs::read_to_string(filename)?;
-    let parts = input.trim().split(',').map(parse_instr).collect::<Vec<_>>();
-    let mut boxes: [Vec<(&[u8], u8)>; 256] = std::array::from_fn(|_| Vec::new());
-    for part in parts {
-        match part {
-            Instr::Rem(hash, part) => {
-                boxes[hash as usize].retain(|(p, _)| p != &part);
+impl std::fmt::Display for LensBoxes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in 0..self.buckets() {
+            let mut lenses = self.lenses(i).peekable();
+            if lenses.peek().is_none() {
+                continue;
             }
-            Instr::Set(hash, part, lens) => {
-                let mut found = false;
-                boxes[hash as usize].iter_mut().for_each(|(p, l)| {
-                    if p == &part {
-                        found = true;
-                        *l = lens;
-                    }
-                });
-                if !found {
-                    boxes[hash as usize].push((part, lens));
-                }
+            write!(f, "Box {}:", i)?;
+            for (label, focal) in lenses {
+                write!(f, " [{} {}]", String::from_utf8_lossy(label), focal)?;
             }
+            writeln!(f)?;
         }
+        Ok(())
+    }
+}
+
+pub struct Replay {
+    instrs: Vec<Instr>,
+    changes: Vec<Change>,
+}
+
+impl Replay {
+    pub fn new(instrs: Vec<Instr>) -> Self {
+        let mut boxes = LensBoxes::new();
+        let changes = instrs.iter().map(|i| boxes.apply(i)).collect();
+        Replay { instrs, changes }
     }
-    let power = calc_power(&boxes);
-    Ok(power)
+
+    pub fn len(&self) -> usize {
+        self.instrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instrs.is_empty()
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn state_after(&self, step: usize) -> Option<LensBoxes> {
+        let mut boxes = LensBoxes::new();
+        for instr in self.instrs.get(..=step)? {
+            boxes.apply(instr);
+        }
+        Some(boxes)
+    }
+
+    pub fn first_appearance(&self, label: &[u8]) -> Option<usize> {
+        self.changes
+            .iter()
+            .position(|c| matches!(c, Change::Added(_, l, _) if l == label))
+    }
+
+    pub fn first_disappearance(&self, label: &[u8]) -> Option<usize> {
+        self.changes
+            .iter()
+            .position(|c| matches!(c, Change::Removed(_, l, _) if l == label))
+    }
+}
+
+pub fn load_replay(filename: &str) -> Result<Replay> {
+    let input = std::fs::read_to_string(filename)?;
+    Ok(Replay::new(parse_sequence(&input)?))
+}
+
+#[test_case("inputs/example-15-1.txt", "qp" => matches Ok((Some(2), Some(4))))]
+#[test_case("inputs/example-15-1.txt", "cm" => matches Ok((Some(3), None)))]
+#[test_case("inputs/example-15-1.txt", "xx" => matches Ok((None, None)))]
+pub fn label_lifetime(filename: &str, label: &str) -> Result<(Option<usize>, Option<usize>)> {
+    let replay = load_replay(filename)?;
+    let label = label.as_bytes();
+    Ok((
+        replay.first_appearance(label),
+        replay.first_disappearance(label),
+    ))
+}
+
+#[test_case("inputs/example-15-1.txt", 3 => matches Ok(11))]
+#[test_case("inputs/example-15-1.txt", 10 => matches Ok(145))]
+#[test_case("inputs/example-15-1.txt", 11 => matches Err(_))]
+pub fn power_after(filename: &str, step: usize) -> Result<i64> {
+    let replay = load_replay(filename)?;
+    let boxes = replay
+        .state_after(step)
+        .ok_or_else(|| anyhow::anyhow!("Step {} is past the last instruction", step))?;
+    Ok(boxes.focusing_power())
+}
+
+#[test_case("inputs/example-15-1.txt", 256 => matches Ok(145))]
+#[test_case("inputs/example-15-1.txt", 3 => matches Ok(76))]
+#[test_case("inputs/example-15-1.txt", 1 => matches Ok(76))]
+#[test_case("inputs/example-15-1.txt", 0 => panics "LensBoxes needs at least one bucket")]
+pub fn focusing_power(filename: &str, buckets: usize) -> Result<i64> {
+    let input = std::fs::read_to_string(filename)?;
+    let mut boxes = LensBoxes::with_hasher(buckets, |s| calc_hash(s) as usize);
+    for instr in parse_sequence(&input)? {
+        boxes.apply(&instr);
+    }
+    Ok(boxes.focusing_power())
+}
+
+#[test_case("inputs/example-15-1.txt" => matches Ok(145))]
+#[test_case("inputs/input-15.txt" => matches Ok(200277))]
+#[test_case("inputs/example-15-2.txt" => matches Err(e) if e.downcast_ref() == Some(&InstrError::MissingFocalLength(2)))]
+#[test_case("inputs/example-15-3.txt" => matches Err(e) if e.downcast_ref() == Some(&InstrError::EmptyLabel(1)))]
+pub fn puzzle2(filename: &str) -> Result<i64> {
+    focusing_power(filename, 256)
 }
@@ -1,30 +1,146 @@
 use anyhow::Result;
 use pathfinding::matrix::{directions, Matrix};
-use std::collections::HashSet;
+use petgraph::graph::{DiGraph, NodeIndex};
+use std::collections::{HashMap, HashSet};
 use test_case::test_case;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Tile {
+pub enum Tile {
     Empty,
     Slash,
     Backslash,
     Versplit,
     Horsplit,
+    Absorber,
+    Diode((isize, isize)),
+    Trisplit,
 }
 
-fn parse_input(filename: &str) -> Result<Matrix<Tile>> {
+#[derive(Debug, Clone)]
+pub struct TileSet {
+    tiles: HashMap<char, Tile>,
+}
+
+impl TileSet {
+    pub fn puzzle() -> Self {
+        let tiles = [
+            ('.', Tile::Empty),
+            ('/', Tile::Slash),
+            ('\\', Tile::Backslash),
+            ('|', Tile::Versplit),
+            ('-', Tile::Horsplit),
+        ];
+        TileSet {
+            tiles: tiles.into_iter().collect(),
+        }
+    }
+
+    pub fn extended() -> Self {
+        Self::puzzle()
+            .with('#', Tile::Absorber)
+            .with('^', Tile::Diode(directions::N))
+            .with('>', Tile::Diode(directions::E))
+            .with('v', Tile::Diode(directions::S))
+            .with('<', Tile::Diode(directions::W))
+            .with('+', Tile::Trisplit)
+    }
+
+    pub fn with(mut self, c: char, tile: Tile) -> Self {
+        self.tiles.insert(c, tile);
+        self
+    }
+
+    fn char_of(&self, tile: Tile) -> char {
+        self.tiles
+            .iter()
+            .filter(|&(_, &t)| t == tile)
+            .map(|(&c, _)| c)
+            .min()
+            .unwrap_or('?')
+    }
+}
+
+fn parse_with(filename: &str, tiles: &TileSet) -> Result<Matrix<Tile>> {
     let input = std::fs::read_to_string(filename)?;
-    let ret = Matrix::from_rows(input.lines().filter(|l| !l.is_empty()).map(|l| {
-        l.chars().map(|c| match c {
-            '.' => Tile::Empty,
-            '/' => Tile::Slash,
-            '\\' => Tile::Backslash,
-            '|' => Tile::Versplit,
-            '-' => Tile::Horsplit,
-            _ => unreachable!(),
+    let rows = input
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            l.chars()
+                .map(|c| {
+                    tiles
+                        .tiles
+                        .get(&c)
+                        .copied()
+                        .ok_or_else(|| anyhow::anyhow!("Unknown tile {:?}", c))
+                })
+                .collect::<Result<Vec<_>>>()
         })
-    }))?;
-    Ok(ret)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Matrix::from_rows(rows)?)
+}
+
+fn parse_input(filename: &str) -> Result<Matrix<Tile>> {
+    parse_with(filename, &TileSet::puzzle())
+}
+
+const N: &[(isize, isize)] = &[directions::N];
+const E: &[(isize, isize)] = &[directions::E];
+const S: &[(isize, isize)] = &[directions::S];
+const W: &[(isize, isize)] = &[directions::W];
+const NS: &[(isize, isize)] = &[directions::N, directions::S];
+const EW: &[(isize, isize)] = &[directions::E, directions::W];
+
+const ENS: &[(isize, isize)] = &[directions::E, directions::N, directions::S];
+const WNS: &[(isize, isize)] = &[directions::W, directions::N, directions::S];
+const NEW: &[(isize, isize)] = &[directions::N, directions::E, directions::W];
+const SEW: &[(isize, isize)] = &[directions::S, directions::E, directions::W];
+
+fn outgoing(tile: Tile, dir: (isize, isize)) -> &'static [(isize, isize)] {
+    match (tile, dir) {
+        (Tile::Absorber, _) => &[],
+        (Tile::Diode(d), _) if d != dir => &[],
+        (Tile::Trisplit, directions::N) => NEW,
+        (Tile::Trisplit, directions::E) => ENS,
+        (Tile::Trisplit, directions::S) => SEW,
+        (Tile::Trisplit, _) => WNS,
+        (Tile::Slash, directions::E) | (Tile::Backslash, directions::W) => N,
+        (Tile::Slash, directions::N) | (Tile::Backslash, directions::S) => E,
+        (Tile::Slash, directions::W) | (Tile::Backslash, directions::E) => S,
+        (Tile::Slash, directions::S) | (Tile::Backslash, directions::N) => W,
+        (Tile::Versplit, directions::E | directions::W) => NS,
+        (Tile::Horsplit, directions::N | directions::S) => EW,
+        (_, directions::N) => N,
+        (_, directions::E) => E,
+        (_, directions::S) => S,
+        _ => W,
+    }
+}
+
+fn dir_bit(dir: (isize, isize)) -> u8 {
+    match dir {
+        directions::N => 1,
+        directions::E => 2,
+        directions::S => 4,
+        _ => 8,
+    }
+}
+
+pub fn energize(map: &Matrix<Tile>, pos: (usize, usize), dir: (isize, isize)) -> Matrix<u8> {
+    let mut seen = Matrix::new(map.rows, map.columns, 0u8);
+    let mut rays = vec![(pos, dir)];
+    while let Some((p, dir)) = rays.pop() {
+        if seen[p] & dir_bit(dir) != 0 {
+            continue;
+        }
+        seen[p] |= dir_bit(dir);
+        for &d in outgoing(map[p], dir) {
+            if let Some(next) = map.move_in_direction(p, d) {
+                rays.push((next, d));
+            }
+        }
+    }
+    seen
 }
 
 fn shoot_ray(map: &Matrix<Tile>, pos: (usize, usize), dir: (isize, isize)) -> i64 {
@@ -59,6 +175,14 @@ fn shoot_ray(map: &Matrix<Tile>, pos: (usize, usize), dir: (isize, isize)) -> i6
                     break;
                 }
                 Tile::Horsplit => (),
+                Tile::Absorber => break,
+                Tile::Diode(d) if d != dir => break,
+                Tile::Diode(_) => (),
+                Tile::Trisplit => {
+                    let (left, right) = ((-dir.1, dir.0), (dir.1, -dir.0));
+                    rays.push((map.move_in_direction(p, left), left));
+                    rays.push((map.move_in_direction(p, right), right));
+                }
             }
             pos = map.move_in_direction(p, dir);
         }
@@ -67,6 +191,49 @@ fn shoot_ray(map: &Matrix<Tile>, pos: (usize, usize), dir: (isize, isize)) -> i6
     posmap.len() as i64
 }
 
+pub fn render(map: &Matrix<Tile>, seen: &Matrix<u8>, tiles: &TileSet) -> String {
+    let mut ret = String::new();
+    for row in 0..map.rows {
+        for col in 0..map.columns {
+            let c = match (map[(row, col)], seen[(row, col)]) {
+                (Tile::Empty, 1) => '↑',
+                (Tile::Empty, 2) => '→',
+                (Tile::Empty, 4) => '↓',
+                (Tile::Empty, 8) => '←',
+                (Tile::Empty, b) if b != 0 => char::from_digit(b.count_ones(), 10).unwrap(),
+                (tile, _) => tiles.char_of(tile),
+            };
+            if seen[(row, col)] != 0 {
+                ret += &format!("\x1b[1;33m{}\x1b[0m", c);
+            } else {
+                ret.push(c);
+            }
+        }
+        ret.push('\n');
+    }
+    ret
+}
+
+#[test_case("inputs/example-16-2.txt", &TileSet::extended(), (2, 4), directions::W
+    => matches Ok(s) if s == "..+..\\\n.#...v\n\x1b[1;33m>\x1b[0m\x1b[1;33m←\x1b[0m\x1b[1;33m<\x1b[0m\x1b[1;33m←\x1b[0m\x1b[1;33m←\x1b[0m/\n..|...\n")]
+pub fn render_energized(
+    filename: &str,
+    tiles: &TileSet,
+    pos: (usize, usize),
+    dir: (isize, isize),
+) -> Result<String> {
+    let input = parse_with(filename, tiles)?;
+    let seen = energize(&input, pos, dir);
+    Ok(render(&input, &seen, tiles))
+}
+
+#[test_case("inputs/example-16-2.txt", (0, 0), directions::E => matches Ok(14))]
+#[test_case("inputs/example-16-2.txt", (2, 4), directions::W => matches Ok(5))]
+pub fn energized_extended(filename: &str, pos: (usize, usize), dir: (isize, isize)) -> Result<i64> {
+    let input = parse_with(filename, &TileSet::extended())?;
+    Ok(shoot_ray(&input, pos, dir))
+}
+
 #[test_case("inputs/example-16-1.txt" => matches Ok(46))]
 #[test_case("inputs/input-16.txt" => matches Ok(6978))]
 pub fn puzzle1(filename: &str) -> Result<i64> {
@@ -75,9 +242,170 @@ pub fn puzzle1(filename: &str) -> Result<i64> {
     Ok(ret)
 }
 
+pub type Beam = ((usize, usize), (isize, isize));
+
+pub struct BeamGraph {
+    nodes: HashMap<Beam, NodeIndex>,
+    energized: Vec<i64>,
+}
+
+impl BeamGraph {
+    pub fn new(map: &Matrix<Tile>) -> Self {
+        let mut graph: DiGraph<Vec<(usize, usize)>, ()> = DiGraph::new();
+        let mut nodes: HashMap<Beam, NodeIndex> = HashMap::new();
+        let mut todo: Vec<Beam> = entry_points(map);
+        for &beam in &todo {
+            nodes.insert(beam, graph.add_node(Vec::new()));
+        }
+        while let Some(beam) = todo.pop() {
+            let node = nodes[&beam];
+            let (mut pos, dir) = beam;
+            let mut cells = vec![pos];
+            let mut outs = outgoing(map[pos], dir);
+            while outs == [dir] {
+                match map.move_in_direction(pos, dir) {
+                    Some(p) => {
+                        pos = p;
+                        cells.push(pos);
+                        outs = outgoing(map[pos], dir);
+                    }
+                    None => {
+                        outs = &[];
+                    }
+                }
+            }
+            graph[node] = cells;
+            for &d in outs {
+                let Some(p) = map.move_in_direction(pos, d) else {
+                    continue;
+                };
+                let next = *nodes.entry((p, d)).or_insert_with(|| {
+                    todo.push((p, d));
+                    graph.add_node(Vec::new())
+                });
+                graph.add_edge(node, next, ());
+            }
+        }
+        let sccs = petgraph::algo::tarjan_scc(&graph);
+        let mut component = vec![0; graph.node_count()];
+        for (c, scc) in sccs.iter().enumerate() {
+            for &n in scc {
+                component[n.index()] = c;
+            }
+        }
+        let successors: Vec<Vec<usize>> = sccs
+            .iter()
+            .enumerate()
+            .map(|(c, scc)| {
+                let mut next: Vec<usize> = scc
+                    .iter()
+                    .flat_map(|&n| graph.neighbors(n))
+                    .map(|m| component[m.index()])
+                    .filter(|&mc| mc != c)
+                    .collect();
+                next.sort_unstable();
+                next.dedup();
+                next
+            })
+            .collect();
+        let mut waiting = vec![0; sccs.len()];
+        for &mc in successors.iter().flatten() {
+            waiting[mc] += 1;
+        }
+        // Tarjan yields components successors first, so each set is built
+        // from finished ones. A set is dropped once the last component
+        // leading into it has merged it, so only the components on the
+        // frontier of that order hold a set; that is still one bit per cell
+        // for each of them, but no longer for every component.
+        let words = (map.rows * map.columns).div_ceil(64);
+        let mut bits: Vec<Vec<u64>> = vec![Vec::new(); sccs.len()];
+        let mut counts: Vec<i64> = Vec::new();
+        for (c, scc) in sccs.iter().enumerate() {
+            let mut set = vec![0u64; words];
+            for &n in scc {
+                for &(y, x) in &graph[n] {
+                    let i = y * map.columns + x;
+                    set[i / 64] |= 1 << (i % 64);
+                }
+            }
+            for &mc in &successors[c] {
+                for (a, b) in set.iter_mut().zip(&bits[mc]) {
+                    *a |= b;
+                }
+                waiting[mc] -= 1;
+                if waiting[mc] == 0 {
+                    bits[mc] = Vec::new();
+                }
+            }
+            counts.push(set.iter().map(|w| w.count_ones() as i64).sum());
+            if waiting[c] > 0 {
+                bits[c] = set;
+            }
+        }
+        let energized = component.iter().map(|&c| counts[c]).collect();
+        BeamGraph { nodes, energized }
+    }
+
+    pub fn energized(&self, pos: (usize, usize), dir: (isize, isize)) -> Option<i64> {
+        let node = self.nodes.get(&(pos, dir))?;
+        Some(self.energized[node.index()])
+    }
+}
+
+fn entry_points(map: &Matrix<Tile>) -> Vec<Beam> {
+    let mut ret = Vec::new();
+    for y in 0..map.rows {
+        ret.push(((y, 0), directions::E));
+        ret.push(((y, map.columns - 1), directions::W));
+    }
+    for x in 0..map.columns {
+        ret.push(((0, x), directions::S));
+        ret.push(((map.rows - 1, x), directions::N));
+    }
+    ret
+}
+
+pub fn best_entry(filename: &str) -> Result<(Beam, i64)> {
+    let input = parse_input(filename)?;
+    let graph = BeamGraph::new(&input);
+    let ret = entry_points(&input)
+        .into_iter()
+        .map(|beam| (beam, graph.energized(beam.0, beam.1).unwrap()))
+        .max_by_key(|&(_, e)| e)
+        .unwrap();
+    Ok(ret)
+}
+
+#[test_case("inputs/example-16-1.txt", &TileSet::puzzle() => matches Ok(v) if v.is_empty())]
+#[test_case("inputs/example-16-2.txt", &TileSet::extended() => matches Ok(v) if v.is_empty())]
+#[test_case("inputs/input-16.txt", &TileSet::puzzle() => matches Ok(v) if v.is_empty())]
+pub fn disagreements(filename: &str, tiles: &TileSet) -> Result<Vec<Beam>> {
+    let input = parse_with(filename, tiles)?;
+    let graph = BeamGraph::new(&input);
+    let ret = entry_points(&input)
+        .into_iter()
+        .filter(|&(pos, dir)| {
+            let expected = shoot_ray(&input, pos, dir);
+            let cells = energize(&input, pos, dir)
+                .values()
+                .filter(|&&v| v != 0)
+                .count();
+            cells as i64 != expected || graph.energized(pos, dir) != Some(expected)
+        })
+        .collect();
+    Ok(ret)
+}
+
 #[test_case("inputs/example-16-1.txt" => matches Ok(51))]
 #[test_case("inputs/input-16.txt" => matches Ok(7315))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
+    let (_, ret) = best_entry(filename)?;
+    Ok(ret)
+}
+
+#[test_case("inputs/example-16-1.txt" => matches Ok(51))]
+#[test_case("inputs/input-16.txt" => matches Ok(7315))]
+pub fn puzzle2_reference(filename: &str) -> Result<i64> {
     let input = parse_input(filename)?;
     let mut max = 0;
     for y in 0..input.rows {
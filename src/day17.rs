@@ -1,3 +1,4 @@
+use crate::dial::dial;
 use anyhow::Result;
 use pathfinding::directed::astar::astar;
 use pathfinding::matrix::{directions, Matrix};
@@ -16,88 +17,252 @@ fn parse_input(filename: &str) -> Result<Matrix<u8>> {
 
 type State = ((usize, usize), (isize, isize), usize);
 
-fn successors1(input: &Matrix<u8>, state: State) -> impl IntoIterator<Item = (State, usize)> + '_ {
+const STILL: (isize, isize) = (0, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turns {
+    NoReversal,
+    ReversalAfter(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crucible {
+    pub min_run: usize,
+    pub max_run: usize,
+    pub turns: Turns,
+}
+
+impl Crucible {
+    pub fn normal() -> Self {
+        Crucible {
+            min_run: 1,
+            max_run: 3,
+            turns: Turns::NoReversal,
+        }
+    }
+
+    pub fn ultra() -> Self {
+        Crucible {
+            min_run: 4,
+            max_run: 10,
+            turns: Turns::NoReversal,
+        }
+    }
+
+    fn allows(&self, dir: (isize, isize), count: usize, d: (isize, isize)) -> bool {
+        if dir == STILL {
+            true
+        } else if d == dir {
+            count < self.max_run
+        } else if count < self.min_run {
+            false
+        } else if d == (-dir.0, -dir.1) {
+            match self.turns {
+                Turns::NoReversal => false,
+                Turns::ReversalAfter(n) => count >= n,
+            }
+        } else {
+            true
+        }
+    }
+}
+
+fn successors<'a>(
+    input: &'a Matrix<u8>,
+    crucible: &'a Crucible,
+    state: State,
+) -> impl Iterator<Item = (State, usize)> + 'a {
     let (pos, dir, count) = state;
-    [
-        (directions::N, directions::S),
-        (directions::S, directions::N),
-        (directions::E, directions::W),
-        (directions::W, directions::E),
-    ]
-    .iter()
-    .filter_map(move |&(d, o)| {
-        if o == dir {
-            None
-        } else if d == dir && count >= 3 {
-            None
-        } else if let Some(p) = input.move_in_direction(pos, d) {
+    [directions::N, directions::E, directions::S, directions::W]
+        .into_iter()
+        .filter(move |&d| crucible.allows(dir, count, d))
+        .filter_map(move |d| {
+            let p = input.move_in_direction(pos, d)?;
             let c = if d == dir { count + 1 } else { 1 };
             Some(((p, d, c), input[p] as usize))
-        } else {
-            None
-        }
-    })
+        })
 }
 
 fn heuristic(pos: (usize, usize), goal: (usize, usize)) -> usize {
     pos.0.abs_diff(goal.0) + pos.1.abs_diff(goal.1)
 }
 
-fn success(pos: (usize, usize), goal: (usize, usize)) -> bool {
-    pos == goal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub heat_loss: usize,
+    pub path: Vec<(usize, usize)>,
+    pub headings: Vec<(isize, isize)>,
+    pub turns: Vec<((usize, usize), (isize, isize))>,
 }
 
-#[test_case("inputs/example-17-1.txt" => matches Ok(102))]
-#[test_case("inputs/input-17.txt" => matches Ok(1076))]
-pub fn puzzle1(filename: &str) -> Result<i64> {
+impl Route {
+    fn from_states(states: &[State], heat_loss: usize) -> Self {
+        let path = states.iter().map(|&(pos, _, _)| pos).collect();
+        let headings: Vec<_> = states.iter().map(|&(_, dir, _)| dir).collect();
+        let turns = states
+            .windows(2)
+            .filter(|w| w[0].1 != STILL && w[0].1 != w[1].1)
+            .map(|w| (w[0].0, w[1].1))
+            .collect();
+        Route {
+            heat_loss,
+            path,
+            headings,
+            turns,
+        }
+    }
+
+    pub fn render(&self, map: &Matrix<u8>) -> String {
+        let mut chars = Matrix::from_fn(map.rows, map.columns, |p| (b'0' + map[p]) as char);
+        for (&pos, &dir) in self.path.iter().zip(&self.headings).skip(1) {
+            chars[pos] = match dir {
+                directions::N => '^',
+                directions::E => '>',
+                directions::S => 'v',
+                _ => '<',
+            };
+        }
+        let mut ret = String::new();
+        for row in chars.iter() {
+            ret.extend(row);
+            ret.push('\n');
+        }
+        ret
+    }
+}
+
+struct StateIndex {
+    columns: usize,
+    runs: usize,
+}
+
+const HEADINGS: [(isize, isize); 5] = [
+    directions::N,
+    directions::E,
+    directions::S,
+    directions::W,
+    STILL,
+];
+
+impl StateIndex {
+    fn len(&self, map: &Matrix<u8>) -> usize {
+        map.rows * map.columns * HEADINGS.len() * self.runs
+    }
+
+    fn index(&self, (pos, dir, count): State) -> usize {
+        let d = HEADINGS.iter().position(|&h| h == dir).unwrap();
+        ((pos.0 * self.columns + pos.1) * HEADINGS.len() + d) * self.runs + count
+    }
+
+    fn state(&self, index: usize) -> State {
+        let (rest, count) = (index / self.runs, index % self.runs);
+        let (cell, d) = (rest / HEADINGS.len(), rest % HEADINGS.len());
+        (
+            (cell / self.columns, cell % self.columns),
+            HEADINGS[d],
+            count,
+        )
+    }
+}
+
+pub fn solve(
+    map: &Matrix<u8>,
+    crucible: &Crucible,
+    start: (usize, usize),
+    goal: (usize, usize),
+) -> Option<Route> {
+    assert!(
+        crucible.max_run >= 1 && crucible.min_run <= crucible.max_run,
+        "crucible runs must satisfy min_run <= max_run and max_run >= 1"
+    );
+    let index = StateIndex {
+        columns: map.columns,
+        runs: crucible.max_run + 1,
+    };
+    let (path, heat_loss) = dial(
+        index.len(map),
+        map.values().copied().max().unwrap_or(0) as usize,
+        index.index((start, STILL, 0)),
+        |i| successors(map, crucible, index.state(i)).map(|(s, c)| (index.index(s), c)),
+        |i| {
+            let (pos, _, count) = index.state(i);
+            pos == goal && count >= crucible.min_run
+        },
+    )?;
+    let states: Vec<State> = path.into_iter().map(|i| index.state(i)).collect();
+    Some(Route::from_states(&states, heat_loss))
+}
+
+pub fn solve_astar(
+    map: &Matrix<u8>,
+    crucible: &Crucible,
+    start: (usize, usize),
+    goal: (usize, usize),
+) -> Option<Route> {
+    let (states, heat_loss) = astar(
+        &(start, STILL, 0),
+        |&state| successors(map, crucible, state),
+        |&state| heuristic(state.0, goal),
+        |&(pos, _, count)| pos == goal && count >= crucible.min_run,
+    )?;
+    Some(Route::from_states(&states, heat_loss))
+}
+
+#[test_case("inputs/example-17-1.txt", Crucible::normal() => matches Ok(Route { heat_loss: 102, .. }))]
+#[test_case("inputs/example-17-2.txt", Crucible::ultra() => matches Ok(Route { heat_loss: 71, ref turns, .. }) if turns.len() == 2)]
+#[test_case("inputs/example-17-2.txt", Crucible { min_run: 1, max_run: 2, turns: Turns::NoReversal } => matches Ok(Route { heat_loss: 83, .. }))]
+#[test_case("inputs/example-17-2.txt", Crucible { min_run: 1, max_run: 1, turns: Turns::NoReversal } => matches Ok(Route { heat_loss: 125, .. }))]
+#[test_case("inputs/example-17-2.txt", Crucible { min_run: 6, max_run: 10, turns: Turns::NoReversal } => matches Err(_))]
+#[test_case("inputs/example-17-2.txt", Crucible { min_run: 0, max_run: 0, turns: Turns::NoReversal } => panics "crucible runs")]
+#[test_case("inputs/example-17-2.txt", Crucible { min_run: 4, max_run: 3, turns: Turns::NoReversal } => panics "crucible runs")]
+pub fn route(filename: &str, crucible: Crucible) -> Result<Route> {
     let input = parse_input(filename)?;
     let goal = (input.rows - 1, input.columns - 1);
-    let (_, cost) = astar(
-        &((0, 0), directions::W, 0),
-        |&state| successors1(&input, state),
-        |&state| heuristic(state.0, goal),
-        |&state| success(state.0, goal),
-    )
-    .expect("no path found");
-    Ok(cost as i64)
+    solve(&input, &crucible, (0, 0), goal).ok_or_else(|| anyhow::anyhow!("No path found"))
 }
 
-fn successors2(input: &Matrix<u8>, state: State) -> impl IntoIterator<Item = (State, usize)> + '_ {
-    let (pos, dir, count) = state;
-    [
-        (directions::N, directions::S),
-        (directions::S, directions::N),
-        (directions::E, directions::W),
-        (directions::W, directions::E),
-    ]
-    .iter()
-    .filter_map(move |&(d, o)| {
-        if o == dir && count > 0 {
-            None
-        } else if d == dir && count >= 10 {
-            None
-        } else if d != dir && count > 0 && count < 4 {
-            None
-        } else if let Some(p) = input.move_in_direction(pos, d) {
-            let c = if d == dir { count + 1 } else { 1 };
-            Some(((p, d, c), input[p] as usize))
-        } else {
-            None
-        }
-    })
+#[test_case("inputs/example-17-1.txt", Turns::NoReversal, (0, 2) => matches Ok(69))]
+#[test_case("inputs/example-17-1.txt", Turns::ReversalAfter(4), (0, 2) => matches Ok(28))]
+#[test_case("inputs/example-17-1.txt", Turns::ReversalAfter(4), (12, 12) => matches Ok(94))]
+pub fn ultra_heat_loss(filename: &str, turns: Turns, goal: (usize, usize)) -> Result<usize> {
+    let input = parse_input(filename)?;
+    let crucible = Crucible {
+        turns,
+        ..Crucible::ultra()
+    };
+    let route =
+        solve(&input, &crucible, (0, 0), goal).ok_or_else(|| anyhow::anyhow!("No path found"))?;
+    Ok(route.heat_loss)
+}
+
+#[test_case("inputs/example-17-2.txt", Crucible::ultra()
+    => matches Ok(s) if s == "1>>>>>>>1111\n9999999v9991\n9999999v9991\n9999999v9991\n9999999v>>>>\n")]
+pub fn render_route(filename: &str, crucible: Crucible) -> Result<String> {
+    let input = parse_input(filename)?;
+    let route = route(filename, crucible)?;
+    Ok(route.render(&input))
+}
+
+#[test_case("inputs/example-17-1.txt" => matches Ok(102))]
+#[test_case("inputs/input-17.txt" => matches Ok(1076))]
+pub fn puzzle1(filename: &str) -> Result<i64> {
+    Ok(route(filename, Crucible::normal())?.heat_loss as i64)
 }
 
 #[test_case("inputs/example-17-1.txt" => matches Ok(94))]
 #[test_case("inputs/input-17.txt" => matches Ok(1219))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
+    Ok(route(filename, Crucible::ultra())?.heat_loss as i64)
+}
+
+#[test_case("inputs/example-17-1.txt", Crucible::normal() => matches Ok(102))]
+#[test_case("inputs/input-17.txt", Crucible::normal() => matches Ok(1076))]
+#[test_case("inputs/example-17-1.txt", Crucible::ultra() => matches Ok(94))]
+#[test_case("inputs/input-17.txt", Crucible::ultra() => matches Ok(1219))]
+pub fn heat_loss_astar(filename: &str, crucible: Crucible) -> Result<usize> {
     let input = parse_input(filename)?;
     let goal = (input.rows - 1, input.columns - 1);
-    let (_path, cost) = astar(
-        &((0, 0), directions::W, 0),
-        |&state| successors2(&input, state),
-        |&state| heuristic(state.0, goal),
-        |&state| success(state.0, goal),
-    )
-    .unwrap();
-    Ok(cost as i64)
+    let route = solve_astar(&input, &crucible, (0, 0), goal)
+        .ok_or_else(|| anyhow::anyhow!("No path found"))?;
+    Ok(route.heat_loss)
 }
@@ -1,10 +1,9 @@
+use crate::lagoon::{Dig, Lagoon};
 use anyhow::Result;
 use pathfinding::matrix::directions;
 use test_case::test_case;
 
-type Instr = ((isize, isize), usize, u32);
-
-fn parse_input(filename: &str) -> Result<Vec<Instr>> {
+fn parse_input(filename: &str) -> Result<Vec<Dig>> {
     let input = std::fs::read_to_string(filename)?;
     let ret = input
         .lines()
@@ -20,48 +19,16 @@ fn parse_input(filename: &str) -> Result<Vec<Instr>> {
             };
             let count = count.parse().unwrap();
             let color = u32::from_str_radix(&color[2..color.len() - 1], 16).unwrap();
-            (dir, count, color)
+            Dig { dir, count, color }
         })
         .collect();
     Ok(ret)
 }
 
-#[test_case("inputs/example-18-1.txt" => matches Ok(62))]
-#[test_case("inputs/input-18.txt" => matches Ok(67891))]
-pub fn puzzle1(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    // let mut map = Matrix::new(1000, 1000, 0xffffffu32);
-    // let mut pos = (500, 500);
-    // for (dir, count, color) in input {
-    //     for _ in 0..count {
-    //         pos = map.move_in_direction(pos, dir).unwrap();
-    //         map[pos] = color;
-    //     }
-    // }
-    // let outer = map.bfs_reachable((0, 0), false, |pos| {
-    //     map[pos] == 0xffffffu32
-    // });
-    // for o in outer {
-    //     map[o] = 0u32;
-    // }
-    // let ret = map.values().filter(|v| **v != 0u32).count() as i64;
-    let mut pos = (0, 0);
-    let mut lines = vec![(0, 0)];
-    let mut border = 0;
-    for (dir, count, _) in &input {
-        border += *count as i64;
-        pos.0 += dir.0 * *count as isize;
-        pos.1 += dir.1 * *count as isize;
-        lines.push((pos.0 as i64, pos.1 as i64));
-    }
-    let ret = calc_area(lines) + border / 2 + 1;
-    Ok(ret)
-}
-
-fn fix_input(input: &[Instr]) -> Vec<Instr> {
+fn fix_input(input: &[Dig]) -> Vec<Dig> {
     input
         .iter()
-        .map(|(_, _, color)| {
+        .map(|&Dig { color, .. }| {
             let dir = match color & 0xf {
                 0 => directions::E,
                 1 => directions::S,
@@ -70,33 +37,47 @@ fn fix_input(input: &[Instr]) -> Vec<Instr> {
                 _ => unreachable!(),
             };
             let count = (color >> 4) as usize;
-            (dir, count, *color)
+            Dig { dir, count, color }
         })
         .collect()
 }
 
-fn calc_area(polygon: Vec<(i64, i64)>) -> i64 {
-    let ret = polygon
-        .windows(2)
-        .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
-        .sum::<i64>();
-    ret.abs() / 2
+fn load_lagoon(filename: &str, fixed: bool) -> Result<Lagoon> {
+    let mut input = parse_input(filename)?;
+    if fixed {
+        input = fix_input(&input);
+    }
+    Ok(Lagoon::new(&input)?)
+}
+
+#[test_case("inputs/example-18-1.txt", false => matches Ok((24, 38, 62)))]
+#[test_case("inputs/example-18-1.txt", true => matches Ok((952401738853, 6405262, 952408144115)))]
+#[test_case("inputs/example-18-2.txt", false => matches Err(e) if e.downcast_ref() == Some(&crate::lagoon::PlanError::Unclosed((1, 0))))]
+#[test_case("inputs/example-18-3.txt", false => matches Err(e) if e.downcast_ref() == Some(&crate::lagoon::PlanError::SelfIntersecting(0, 3)))]
+#[test_case("inputs/example-18-4.txt", false => matches Err(e) if e.downcast_ref() == Some(&crate::lagoon::PlanError::SelfIntersecting(1, 2)))]
+pub fn lagoon_area(filename: &str, fixed: bool) -> Result<(i64, i64, i64)> {
+    let lagoon = load_lagoon(filename, fixed)?;
+    Ok((lagoon.interior(), lagoon.boundary(), lagoon.total()))
+}
+
+#[test_case("inputs/example-18-1.txt", false => matches Ok(s) if s == std::fs::read_to_string("inputs/example-18-1.svg").unwrap())]
+pub fn render_svg(filename: &str, fixed: bool) -> Result<String> {
+    Ok(load_lagoon(filename, fixed)?.to_svg())
+}
+
+pub fn write_svg(filename: &str, fixed: bool, output: &str) -> Result<()> {
+    std::fs::write(output, render_svg(filename, fixed)?)?;
+    Ok(())
+}
+
+#[test_case("inputs/example-18-1.txt" => matches Ok(62))]
+#[test_case("inputs/input-18.txt" => matches Ok(67891))]
+pub fn puzzle1(filename: &str) -> Result<i64> {
+    Ok(load_lagoon(filename, false)?.total())
 }
 
 #[test_case("inputs/example-18-1.txt" => matches Ok(952408144115))]
 #[test_case("inputs/input-18.txt" => matches Ok(94116351948493))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let input = fix_input(&input);
-    let mut pos = (0, 0);
-    let mut lines = vec![(0, 0)];
-    let mut border = 0;
-    for (dir, count, _) in &input {
-        border += *count as i64;
-        pos.0 += dir.0 * *count as isize;
-        pos.1 += dir.1 * *count as isize;
-        lines.push((pos.0 as i64, pos.1 as i64));
-    }
-    let ret = calc_area(lines) + border / 2 + 1;
-    Ok(ret)
+    Ok(load_lagoon(filename, true)?.total())
 }
@@ -1,178 +1,829 @@
 use anyhow::Result;
+use num::BigUint;
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::DiGraph;
+use petgraph::visit::Dfs;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use test_case::test_case;
 
+type Cat = usize;
+
+type Part = Vec<i64>;
+
+type Id = usize;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Cat {
-    X = 0,
-    M = 1,
-    A = 2,
-    S = 3,
+enum Target {
+    Accept,
+    Reject,
+    Flow(Id),
 }
 
-type Part = [i64; 4];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
 
-type Id = u32;
+#[derive(Debug)]
+enum Cond {
+    Cmp(Cat, Op, i64),
+    All(Vec<Cond>),
+    Any(Vec<Cond>),
+}
+
+impl Cond {
+    fn mark_categories(&self, mask: &mut [bool]) {
+        match self {
+            Cond::Cmp(cat, _, _) => mask[*cat] = true,
+            Cond::All(conds) | Cond::Any(conds) => {
+                for c in conds {
+                    c.mark_categories(mask);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn eval(&self, part: &[i64]) -> bool {
+        match self {
+            Cond::Cmp(cat, op, val) => {
+                let v = part[*cat];
+                match op {
+                    Op::Lt => v < *val,
+                    Op::Le => v <= *val,
+                    Op::Gt => v > *val,
+                    Op::Ge => v >= *val,
+                    Op::Eq => v == *val,
+                    Op::Ne => v != *val,
+                }
+            }
+            Cond::All(conds) => conds.iter().all(|c| c.eval(part)),
+            Cond::Any(conds) => conds.iter().any(|c| c.eval(part)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    cond: Option<Cond>,
+    target: Target,
+}
 
 #[derive(Debug)]
-enum Rule {
-    Lt(Cat, i64, Id),
-    Gt(Cat, i64, Id),
-    Al(Id),
-}
-
-fn parse_id(s: &str) -> Id {
-    s.as_bytes().iter().fold(0, |acc, c| (acc << 8 | *c as u32))
-}
-
-#[allow(dead_code)]
-fn print_id(id: Id) -> String {
-    let mut ret = String::new();
-    let mut id = id;
-    while id != 0 {
-        ret.push((id & 0xff) as u8 as char);
-        id >>= 8;
-    }
-    ret.chars().rev().collect()
-}
-
-fn parse_cat(s: &str) -> Cat {
-    match s {
-        "x" => Cat::X,
-        "m" => Cat::M,
-        "a" => Cat::A,
-        "s" => Cat::S,
-        _ => unreachable!(),
-    }
-}
-
-fn parse_rule(s: &str) -> Rule {
-    if let Some((cat, rest)) = s.split_once('<') {
-        let (val, dst) = rest.split_once(':').unwrap();
-        let cat = parse_cat(cat);
-        let val = val.parse().unwrap();
-        let dst = parse_id(dst);
-        Rule::Lt(cat, val, dst)
-    } else if let Some((cat, rest)) = s.split_once('>') {
-        let (val, dst) = rest.split_once(':').unwrap();
-        let cat = parse_cat(cat);
-        let val = val.parse().unwrap();
-        let dst = parse_id(dst);
-        Rule::Gt(cat, val, dst)
+struct Workflow {
+    rules: Vec<Rule>,
+    line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkflowError {
+    MissingEntry,
+    Duplicate { name: String, line: usize },
+    Undefined { name: String, line: usize },
+    Unreachable { name: String, line: usize },
+    NoFallback { name: String, line: usize },
+    Cycle { names: Vec<String>, line: usize },
+}
+
+impl std::fmt::Display for WorkflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkflowError::MissingEntry => write!(f, "No `in` workflow"),
+            WorkflowError::Duplicate { name, line } => {
+                write!(f, "Line {}: workflow {} is defined twice", line, name)
+            }
+            WorkflowError::Undefined { name, line } => {
+                write!(f, "Line {}: target {} is not defined", line, name)
+            }
+            WorkflowError::Unreachable { name, line } => {
+                write!(
+                    f,
+                    "Line {}: workflow {} is unreachable from `in`",
+                    line, name
+                )
+            }
+            WorkflowError::NoFallback { name, line } => {
+                write!(
+                    f,
+                    "Line {}: workflow {} does not end with an unconditional rule",
+                    line, name
+                )
+            }
+            WorkflowError::Cycle { names, line } => {
+                write!(
+                    f,
+                    "Line {}: workflows {} form a cycle",
+                    line,
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorkflowError {}
+
+impl WorkflowError {
+    /// Unreachable workflows are dead code; everything else breaks evaluation.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, WorkflowError::Unreachable { .. })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Workflows {
+    categories: Vec<String>,
+    cat_index: HashMap<String, Cat>,
+    names: Vec<String>,
+    index: HashMap<String, Id>,
+    flows: Vec<Option<Workflow>>,
+    duplicates: Vec<(Id, usize)>,
+}
+
+impl Workflows {
+    fn intern(&mut self, name: &str) -> Id {
+        if let Some(&id) = self.index.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.index.insert(name.to_string(), id);
+        self.flows.push(None);
+        id
+    }
+
+    fn category(&mut self, name: &str) -> Cat {
+        if let Some(&cat) = self.cat_index.get(name) {
+            return cat;
+        }
+        let cat = self.categories.len();
+        self.categories.push(name.to_string());
+        self.cat_index.insert(name.to_string(), cat);
+        cat
+    }
+
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    fn target(&mut self, name: &str) -> Target {
+        match name {
+            "A" => Target::Accept,
+            "R" => Target::Reject,
+            _ => Target::Flow(self.intern(name)),
+        }
+    }
+
+    fn define(&mut self, name: &str, rules: Vec<Rule>, line: usize) {
+        let id = self.intern(name);
+        if self.flows[id].is_some() {
+            self.duplicates.push((id, line));
+        } else {
+            self.flows[id] = Some(Workflow { rules, line });
+        }
+    }
+
+    pub fn name(&self, id: Id) -> &str {
+        &self.names[id]
+    }
+
+    fn entry(&self) -> Option<Id> {
+        self.index
+            .get("in")
+            .copied()
+            .filter(|&id| self.flows[id].is_some())
+    }
+
+    fn rules(&self, id: Id) -> &[Rule] {
+        &self.flows[id].as_ref().unwrap().rules
+    }
+
+    fn defined(&self) -> impl Iterator<Item = (Id, &Workflow)> {
+        self.flows
+            .iter()
+            .enumerate()
+            .filter_map(|(id, flow)| Some((id, flow.as_ref()?)))
+    }
+
+    fn line(&self, id: Id) -> usize {
+        self.flows[id].as_ref().map_or(0, |flow| flow.line)
+    }
+
+    pub fn validate(&self) -> Vec<WorkflowError> {
+        let mut ret = Vec::new();
+        let entry = self.entry();
+        if entry.is_none() {
+            ret.push(WorkflowError::MissingEntry);
+        }
+        for &(id, line) in &self.duplicates {
+            ret.push(WorkflowError::Duplicate {
+                name: self.name(id).to_string(),
+                line,
+            });
+        }
+        for (id, flow) in self.defined() {
+            if flow.rules.last().is_none_or(|rule| rule.cond.is_some()) {
+                ret.push(WorkflowError::NoFallback {
+                    name: self.name(id).to_string(),
+                    line: flow.line,
+                });
+            }
+            let mut seen = Vec::new();
+            for rule in &flow.rules {
+                if let Target::Flow(dst) = rule.target {
+                    if self.flows[dst].is_none() && !seen.contains(&dst) {
+                        seen.push(dst);
+                        ret.push(WorkflowError::Undefined {
+                            name: self.name(dst).to_string(),
+                            line: flow.line,
+                        });
+                    }
+                }
+            }
+        }
+        let mut graph: DiGraph<Id, ()> = DiGraph::new();
+        let nodes: Vec<_> = (0..self.names.len()).map(|id| graph.add_node(id)).collect();
+        for (id, flow) in self.defined() {
+            for rule in &flow.rules {
+                if let Target::Flow(dst) = rule.target {
+                    if self.flows[dst].is_some() {
+                        graph.update_edge(nodes[id], nodes[dst], ());
+                    }
+                }
+            }
+        }
+        if let Some(entry) = entry {
+            let mut reachable = vec![false; self.names.len()];
+            let mut dfs = Dfs::new(&graph, nodes[entry]);
+            while let Some(n) = dfs.next(&graph) {
+                reachable[graph[n]] = true;
+            }
+            let mut unreachable: Vec<_> =
+                self.defined().filter(|&(id, _)| !reachable[id]).collect();
+            unreachable.sort_by_key(|(_, flow)| flow.line);
+            for (id, flow) in unreachable {
+                ret.push(WorkflowError::Unreachable {
+                    name: self.name(id).to_string(),
+                    line: flow.line,
+                });
+            }
+        }
+        let mut cycles: Vec<Vec<Id>> = tarjan_scc(&graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || graph.contains_edge(scc[0], scc[0]))
+            .map(|scc| {
+                let mut ids: Vec<Id> = scc.iter().map(|&n| graph[n]).collect();
+                ids.sort_by_key(|&id| self.line(id));
+                ids
+            })
+            .collect();
+        cycles.sort_by_key(|ids| self.line(ids[0]));
+        for ids in cycles {
+            ret.push(WorkflowError::Cycle {
+                names: ids.iter().map(|&id| self.name(id).to_string()).collect(),
+                line: self.line(ids[0]),
+            });
+        }
+        ret
+    }
+
+    #[cfg(test)]
+    fn run(&self, part: &[i64]) -> bool {
+        let mut id = self.entry().unwrap();
+        loop {
+            let target = self
+                .rules(id)
+                .iter()
+                .find(|rule| rule.cond.as_ref().is_none_or(|c| c.eval(part)))
+                .unwrap()
+                .target;
+            match target {
+                Target::Accept => return true,
+                Target::Reject => return false,
+                Target::Flow(dst) => id = dst,
+            }
+        }
+    }
+}
+
+fn parse_cmp(workflows: &mut Workflows, s: &str) -> Option<Cond> {
+    let ops = [
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+    let (cat, op, val) = ops.iter().find_map(|&(token, op)| {
+        let (cat, val) = s.split_once(token)?;
+        Some((cat.trim(), op, val.trim()))
+    })?;
+    if cat.is_empty() || !cat.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let val = val.parse().ok()?;
+    Some(Cond::Cmp(workflows.category(cat), op, val))
+}
+
+fn parse_cond(workflows: &mut Workflows, s: &str) -> Option<Cond> {
+    let mut any = s
+        .split("||")
+        .map(|term| {
+            let mut all = term
+                .split("&&")
+                .map(|atom| parse_cmp(workflows, atom))
+                .collect::<Option<Vec<_>>>()?;
+            Some(if all.len() == 1 {
+                all.pop().unwrap()
+            } else {
+                Cond::All(all)
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(if any.len() == 1 {
+        any.pop().unwrap()
     } else {
-        let dst = parse_id(s);
-        Rule::Al(dst)
+        Cond::Any(any)
+    })
+}
+
+fn parse_rule(workflows: &mut Workflows, s: &str) -> Option<Rule> {
+    match s.split_once(':') {
+        Some((cond, dst)) => Some(Rule {
+            cond: Some(parse_cond(workflows, cond)?),
+            target: workflows.target(dst),
+        }),
+        None => Some(Rule {
+            cond: None,
+            target: workflows.target(s),
+        }),
     }
 }
 
-fn parse_input(filename: &str) -> Result<(HashMap<Id, Vec<Rule>>, Vec<Part>)> {
+fn parse_unchecked(filename: &str) -> Result<(Workflows, Vec<Part>)> {
     let input = std::fs::read_to_string(filename)?;
-    let (workflows, parts) = input.split_once("\n\n").unwrap();
-    // Example: px{a<2006:qkq,m>2090:A,rfg}
-    let workflows: HashMap<Id, Vec<Rule>> = workflows
+    let (flows, parts) = input.split_once("\n\n").unwrap();
+    let mut workflows = Workflows::default();
+    // Example: {x=787,m=2655,a=1222,s=2876}
+    let ratings: Vec<Vec<(Cat, i64)>> = parts
         .lines()
         .map(|l| {
-            let (id, rest) = l.split_once('{').unwrap();
-            let id = parse_id(id);
-            let rules = rest[..rest.len() - 1].split(',').map(parse_rule).collect();
-            (id, rules)
+            l[1..l.len() - 1]
+                .split(',')
+                .map(|p| {
+                    let (k, v) = p.split_once('=').unwrap();
+                    (workflows.category(k), v.parse().unwrap())
+                })
+                .collect()
         })
         .collect();
-    // Example: {x=787,m=2655,a=1222,s=2876}
-    let parts: Vec<Part> = parts
-        .lines()
-        .map(|l| {
-            let mut ret = [0; 4];
-            for p in l[1..l.len() - 1].split(',') {
-                let (k, v) = p.split_once('=').unwrap();
-                let v = v.parse().unwrap();
-                ret[parse_cat(k) as usize] = v;
+    // Example: px{a<2006:qkq,m>2090:A,rfg}
+    for (i, l) in flows.lines().enumerate() {
+        let (name, rest) = l.split_once('{').unwrap();
+        let rules = rest[..rest.len() - 1]
+            .split(',')
+            .map(|r| {
+                parse_rule(&mut workflows, r)
+                    .ok_or_else(|| anyhow::anyhow!("Line {}: invalid rule {:?}", i + 1, r))
+            })
+            .collect::<Result<_>>()?;
+        workflows.define(name, rules, i + 1);
+    }
+    let parts = ratings
+        .iter()
+        .enumerate()
+        .map(|(i, rating)| {
+            let mut part = vec![None; workflows.categories.len()];
+            for &(cat, v) in rating {
+                part[cat] = Some(v);
             }
-            ret
+            part.iter()
+                .enumerate()
+                .map(|(cat, v)| {
+                    v.ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Part {} has no {} rating",
+                            i + 1,
+                            workflows.categories[cat]
+                        )
+                    })
+                })
+                .collect()
         })
-        .collect();
+        .collect::<Result<_>>()?;
+    Ok((workflows, parts))
+}
+
+fn parse_input(filename: &str) -> Result<(Workflows, Vec<Part>)> {
+    let (workflows, parts) = parse_unchecked(filename)?;
+    if let Some(err) = workflows.validate().into_iter().find(|e| e.is_fatal()) {
+        return Err(err.into());
+    }
     Ok((workflows, parts))
 }
 
+#[test_case("inputs/example-19-1.txt" => matches Ok(ref e) if e.is_empty())]
+#[test_case("inputs/example-19-3.txt" => matches Ok(ref e) if e == &[
+    WorkflowError::Undefined { name: "zz".to_string(), line: 5 },
+    WorkflowError::Unreachable { name: "dd".to_string(), line: 4 },
+    WorkflowError::Cycle { names: vec!["bb".to_string()], line: 3 },
+    WorkflowError::Cycle { names: vec!["ee".to_string(), "ff".to_string()], line: 6 },
+])]
+#[test_case("inputs/example-19-4.txt" => matches Ok(ref e) if e == &[
+    WorkflowError::MissingEntry,
+    WorkflowError::Duplicate { name: "aa".to_string(), line: 2 },
+])]
+#[test_case("inputs/example-19-7.txt" => matches Ok(ref e) if e == &[
+    WorkflowError::Unreachable { name: "dd".to_string(), line: 2 },
+])]
+#[test_case("inputs/example-19-8.txt" => matches Ok(ref e) if e == &[
+    WorkflowError::NoFallback { name: "in".to_string(), line: 1 },
+])]
+pub fn check_workflows(filename: &str) -> Result<Vec<WorkflowError>> {
+    let (workflows, _) = parse_unchecked(filename)?;
+    Ok(workflows.validate())
+}
+
 #[test_case("inputs/example-19-1.txt" => matches Ok(19114))]
+#[test_case("inputs/example-19-2.txt" => matches Ok(19114))]
+#[test_case("inputs/example-19-3.txt" => matches Err(_))]
+#[test_case("inputs/example-19-5.txt" => matches Ok(19114))]
+#[test_case("inputs/example-19-6.txt" => matches Ok(63))]
+#[test_case("inputs/example-19-7.txt" => matches Ok(10))]
+#[test_case("inputs/example-19-8.txt" => matches Err(e) if e.downcast_ref() == Some(&WorkflowError::NoFallback { name: "in".to_string(), line: 1 }))]
+#[test_case("inputs/example-19-9.txt" => matches Ok(10))]
+#[test_case("inputs/example-19-10.txt" => matches Ok(40))]
+#[test_case("inputs/example-19-11.txt" => matches Ok(44))]
 #[test_case("inputs/input-19.txt" => matches Ok(456651))]
 pub fn puzzle1(filename: &str) -> Result<i64> {
     let (workflows, parts) = parse_input(filename)?;
+    let tree = DecisionTree::new(&workflows);
     let ret = parts
         .iter()
-        .map(|part| {
-            let mut id = parse_id("in");
-            loop {
-                let rules = workflows.get(&id).unwrap();
-                for rule in rules {
-                    match rule {
-                        Rule::Lt(cat, val, dst) => {
-                            if part[*cat as usize] < *val {
-                                id = *dst;
-                                break;
-                            }
-                        }
-                        Rule::Gt(cat, val, dst) => {
-                            if part[*cat as usize] > *val {
-                                id = *dst;
-                                break;
-                            }
-                        }
-                        Rule::Al(dst) => {
-                            id = *dst;
-                            break;
-                        }
-                    }
-                }
-                if id == parse_id("A") {
-                    return part.iter().sum();
-                } else if id == parse_id("R") {
-                    return 0;
-                }
+        .filter(|part| tree.accepts(part))
+        .map(|part| part.iter().sum::<i64>())
+        .sum();
+    Ok(ret)
+}
+
+pub type Bounds = Vec<(i64, i64)>;
+
+type NodeId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Node {
+    Leaf(bool),
+    Split {
+        cat: Cat,
+        below: i64,
+        lo: NodeId,
+        hi: NodeId,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecisionTree {
+    categories: usize,
+    nodes: Vec<Node>,
+    root: NodeId,
+}
+
+type Cont<'a> = &'a dyn Fn(&Bounds) -> NodeId;
+
+fn mark_tested(workflows: &Workflows, id: Id, tested: &mut Vec<Vec<Vec<bool>>>) {
+    if !tested[id].is_empty() {
+        return;
+    }
+    let rules = workflows.rules(id);
+    let mut masks = vec![vec![false; workflows.categories.len()]; rules.len() + 1];
+    for (i, rule) in rules.iter().enumerate().rev() {
+        let mut mask = masks[i + 1].clone();
+        if let Some(cond) = &rule.cond {
+            cond.mark_categories(&mut mask);
+        }
+        if let Target::Flow(dst) = rule.target {
+            mark_tested(workflows, dst, tested);
+            for (m, &t) in mask.iter_mut().zip(&tested[dst][0]) {
+                *m |= t;
             }
+        }
+        masks[i] = mask;
+    }
+    tested[id] = masks;
+}
+
+/// Compiles workflows into a decision diagram. Equal nodes are stored once,
+/// and each rule is compiled once per region of the categories that it and
+/// the rules after it still test, so chains of rules grow the diagram
+/// linearly rather than copying the rest of the chain into every branch.
+struct Compiler<'a> {
+    workflows: &'a Workflows,
+    tested: Vec<Vec<Vec<bool>>>,
+    nodes: RefCell<Vec<Node>>,
+    ids: RefCell<HashMap<Node, NodeId>>,
+    memo: RefCell<HashMap<(Id, usize, Bounds), NodeId>>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(workflows: &'a Workflows) -> Self {
+        let mut tested = vec![Vec::new(); workflows.names.len()];
+        for (id, _) in workflows.defined() {
+            mark_tested(workflows, id, &mut tested);
+        }
+        Compiler {
+            workflows,
+            tested,
+            nodes: RefCell::new(Vec::new()),
+            ids: RefCell::new(HashMap::new()),
+            memo: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn node(&self, node: Node) -> NodeId {
+        *self.ids.borrow_mut().entry(node).or_insert_with(|| {
+            let mut nodes = self.nodes.borrow_mut();
+            nodes.push(node);
+            nodes.len() - 1
         })
-        .sum::<i64>();
-    Ok(ret)
+    }
+
+    fn split(&self, cat: Cat, below: i64, bounds: &Bounds, lo: Cont, hi: Cont) -> NodeId {
+        let (min, max) = bounds[cat];
+        if below <= min {
+            return hi(bounds);
+        } else if below > max {
+            return lo(bounds);
+        }
+        let mut lo_bounds = bounds.clone();
+        lo_bounds[cat].1 = below - 1;
+        let mut hi_bounds = bounds.clone();
+        hi_bounds[cat].0 = below;
+        let (lo, hi) = (lo(&lo_bounds), hi(&hi_bounds));
+        if lo == hi {
+            lo
+        } else {
+            self.node(Node::Split { cat, below, lo, hi })
+        }
+    }
+
+    /// Like `split`, with `lo` covering values up to and including `val`.
+    fn split_after(&self, cat: Cat, val: i64, bounds: &Bounds, lo: Cont, hi: Cont) -> NodeId {
+        match val.checked_add(1) {
+            Some(below) => self.split(cat, below, bounds, lo, hi),
+            None => lo(bounds),
+        }
+    }
+
+    fn compile_cond(&self, cond: &Cond, bounds: &Bounds, then: Cont, els: Cont) -> NodeId {
+        match *cond {
+            Cond::Cmp(cat, op, val) => match op {
+                Op::Lt => self.split(cat, val, bounds, then, els),
+                Op::Le => self.split_after(cat, val, bounds, then, els),
+                Op::Gt => self.split_after(cat, val, bounds, els, then),
+                Op::Ge => self.split(cat, val, bounds, els, then),
+                Op::Eq => self.split(cat, val, bounds, els, &|b| {
+                    self.split_after(cat, val, b, then, els)
+                }),
+                Op::Ne => self.split(cat, val, bounds, then, &|b| {
+                    self.split_after(cat, val, b, els, then)
+                }),
+            },
+            Cond::All(ref conds) => self.compile_all(conds, bounds, then, els),
+            Cond::Any(ref conds) => self.compile_any(conds, bounds, then, els),
+        }
+    }
+
+    fn compile_all(&self, conds: &[Cond], bounds: &Bounds, then: Cont, els: Cont) -> NodeId {
+        match conds.split_first() {
+            None => then(bounds),
+            Some((cond, rest)) => {
+                self.compile_cond(cond, bounds, &|b| self.compile_all(rest, b, then, els), els)
+            }
+        }
+    }
+
+    fn compile_any(&self, conds: &[Cond], bounds: &Bounds, then: Cont, els: Cont) -> NodeId {
+        match conds.split_first() {
+            None => els(bounds),
+            Some((cond, rest)) => self.compile_cond(cond, bounds, then, &|b| {
+                self.compile_any(rest, b, then, els)
+            }),
+        }
+    }
+
+    fn compile(&self, target: Target, rule: usize, bounds: &Bounds) -> NodeId {
+        let id = match target {
+            Target::Accept => return self.node(Node::Leaf(true)),
+            Target::Reject => return self.node(Node::Leaf(false)),
+            Target::Flow(id) => id,
+        };
+        let region: Bounds = bounds
+            .iter()
+            .zip(&self.tested[id][rule])
+            .map(|(&b, &t)| if t { b } else { (i64::MIN, i64::MAX) })
+            .collect();
+        let key = (id, rule, region);
+        if let Some(&node) = self.memo.borrow().get(&key) {
+            return node;
+        }
+        let Rule { cond, target: dst } = &self.workflows.rules(id)[rule];
+        let then = |b: &Bounds| self.compile(*dst, 0, b);
+        let ret = match cond {
+            None => then(bounds),
+            Some(cond) => {
+                self.compile_cond(cond, bounds, &then, &|b| self.compile(target, rule + 1, b))
+            }
+        };
+        self.memo.borrow_mut().insert(key, ret);
+        ret
+    }
 }
 
-fn traverse_rules(workflows: &HashMap<Id, Vec<Rule>>, id: Id, mut inputs: [(i64, i64); 4]) -> i64 {
-    if id == parse_id("A") {
-        return inputs.iter().fold(1, |acc, (a, b)| acc * (b - a + 1));
-    } else if id == parse_id("R") {
-        return 0;
+impl DecisionTree {
+    pub fn new(workflows: &Workflows) -> Self {
+        let entry = Target::Flow(workflows.entry().unwrap());
+        let categories = workflows.categories.len();
+        let compiler = Compiler::new(workflows);
+        let root = compiler.compile(entry, 0, &vec![(i64::MIN, i64::MAX); categories]);
+        DecisionTree {
+            categories,
+            nodes: compiler.nodes.into_inner(),
+            root,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut todo = vec![self.root];
+        while let Some(id) = todo.pop() {
+            if std::mem::replace(&mut seen[id], true) {
+                continue;
+            }
+            if let Node::Split { lo, hi, .. } = self.nodes[id] {
+                todo.push(lo);
+                todo.push(hi);
+            }
+        }
+        seen.into_iter().filter(|&s| s).count()
+    }
+
+    pub fn accepts(&self, part: &[i64]) -> bool {
+        let mut id = self.root;
+        loop {
+            match self.nodes[id] {
+                Node::Leaf(accept) => return accept,
+                Node::Split { cat, below, lo, hi } => {
+                    id = if part[cat] < below { lo } else { hi };
+                }
+            }
+        }
     }
-    let rules = workflows.get(&id).unwrap();
-    let mut ret = 0;
-    for rule in rules {
-        match rule {
-            Rule::Lt(cat, val, dst) => {
-                let mut new_inputs = inputs;
-                new_inputs[*cat as usize].0 = std::cmp::min(new_inputs[*cat as usize].0, val - 1);
-                new_inputs[*cat as usize].1 = std::cmp::min(new_inputs[*cat as usize].1, val - 1);
-                inputs[*cat as usize].0 = std::cmp::max(inputs[*cat as usize].0, *val);
-                inputs[*cat as usize].1 = std::cmp::max(inputs[*cat as usize].1, *val);
-                ret += traverse_rules(workflows, *dst, new_inputs);
+
+    pub fn boxes(&self, bounds: &[(i64, i64)]) -> Vec<Bounds> {
+        assert_eq!(bounds.len(), self.categories);
+        let mut ret = Vec::new();
+        let mut todo = vec![(self.root, bounds.to_vec())];
+        while let Some((id, bounds)) = todo.pop() {
+            if bounds.iter().any(|&(min, max)| min > max) {
+                continue;
             }
-            Rule::Gt(cat, val, dst) => {
-                let mut new_inputs = inputs;
-                new_inputs[*cat as usize].0 = std::cmp::max(new_inputs[*cat as usize].0, val + 1);
-                new_inputs[*cat as usize].1 = std::cmp::max(new_inputs[*cat as usize].1, val + 1);
-                inputs[*cat as usize].0 = std::cmp::min(inputs[*cat as usize].0, *val);
-                inputs[*cat as usize].1 = std::cmp::min(inputs[*cat as usize].1, *val);
-                ret += traverse_rules(workflows, *dst, new_inputs);
+            match self.nodes[id] {
+                Node::Leaf(true) => ret.push(bounds),
+                Node::Leaf(false) => {}
+                Node::Split { cat, below, lo, hi } => {
+                    let mut lo_bounds = bounds.clone();
+                    lo_bounds[cat].1 = lo_bounds[cat].1.min(below - 1);
+                    let mut hi_bounds = bounds;
+                    hi_bounds[cat].0 = hi_bounds[cat].0.max(below);
+                    todo.push((hi, hi_bounds));
+                    todo.push((lo, lo_bounds));
+                }
+            }
+        }
+        ret
+    }
+
+    pub fn count(&self, bounds: &[(i64, i64)]) -> BigUint {
+        self.boxes(bounds)
+            .iter()
+            .map(|b| {
+                b.iter()
+                    .map(|(min, max)| BigUint::from((max - min + 1) as u64))
+                    .product::<BigUint>()
+            })
+            .sum()
+    }
+}
+
+#[test_case("inputs/example-19-1.txt" => matches Ok(13))]
+#[test_case("inputs/example-19-10.txt" => matches Ok(42))]
+#[test_case("inputs/example-19-11.txt" => matches Ok(103))]
+#[test_case("inputs/input-19.txt" => matches Ok(814))]
+pub fn tree_size(filename: &str) -> Result<usize> {
+    let (workflows, _) = parse_input(filename)?;
+    Ok(DecisionTree::new(&workflows).size())
+}
+
+#[cfg(test)]
+#[test_case("inputs/example-19-1.txt" => matches Ok(()))]
+#[test_case("inputs/example-19-10.txt" => matches Ok(()))]
+#[test_case("inputs/input-19.txt" => matches Ok(()))]
+fn tree_within_rule_bound(filename: &str) -> Result<()> {
+    let (workflows, _) = parse_input(filename)?;
+    let rules: usize = workflows.defined().map(|(_, flow)| flow.rules.len()).sum();
+    let tree = DecisionTree::new(&workflows);
+    if tree.size() > 2 * rules + 1 {
+        anyhow::bail!("Tree has {} nodes for {} rules", tree.size(), rules);
+    }
+    Ok(())
+}
+
+#[test_case("inputs/example-19-1.txt", &[(1, 4000); 4] => matches Ok(n) if n == 167409079868000u64.into())]
+#[test_case("inputs/example-19-1.txt", &[(1, 1); 4] => matches Ok(n) if n == 1u32.into())]
+#[test_case("inputs/example-19-1.txt", &[(0, 0); 4] => matches Ok(n) if n == 1u32.into())]
+#[test_case("inputs/example-19-1.txt", &[(4001, 5000), (4000, 4000), (1, 1), (2000, 2000)] => matches Ok(n) if n == 0u32.into())]
+#[test_case("inputs/example-19-1.txt", &[(1, 0); 4] => matches Ok(n) if n == 0u32.into())]
+#[test_case("inputs/example-19-1.txt", &[(1, 4000); 3] => matches Err(_))]
+#[test_case("inputs/example-19-5.txt", &[(1, 4000); 4] => matches Ok(n) if n == 167409079868000u64.into())]
+#[test_case("inputs/example-19-6.txt", &[(1, 4000); 6] => matches Ok(n) if n.to_string() == "4087816955009503760016")]
+pub fn count_accepted(filename: &str, bounds: &[(i64, i64)]) -> Result<BigUint> {
+    let (workflows, _) = parse_input(filename)?;
+    if bounds.len() != workflows.categories().len() {
+        anyhow::bail!(
+            "Expected bounds for {} categories, got {}",
+            workflows.categories().len(),
+            bounds.len()
+        );
+    }
+    Ok(DecisionTree::new(&workflows).count(bounds))
+}
+
+#[cfg(test)]
+#[test_case("inputs/example-19-1.txt", &[(1414, 1418), (836, 840), (1715, 1719), (535, 539)] => matches Ok(()))]
+#[test_case("inputs/example-19-1.txt", &[(2438, 2442), (1546, 1550), (3331, 3335), (1349, 1353)] => matches Ok(()))]
+#[test_case("inputs/example-19-1.txt", &[(2660, 2664), (1799, 1803), (2004, 2008), (3446, 3450)] => matches Ok(()))]
+#[test_case("inputs/example-19-5.txt", &[(2004, 2008), (2088, 2092), (1349, 1353), (535, 539)] => matches Ok(()))]
+#[test_case("inputs/example-19-6.txt", &[(1, 6), (1, 6), (1, 6), (1, 6), (1, 6), (1, 6)] => matches Ok(()))]
+#[test_case("inputs/example-19-9.txt", &[(i64::MAX - 2, i64::MAX); 4] => matches Ok(()))]
+#[test_case("inputs/input-19.txt", &[(1, 4000), (1, 1), (1, 1), (1, 1)] => matches Ok(()))]
+#[test_case("inputs/input-19.txt", &[(1, 1), (1, 4000), (1, 1), (4000, 4000)] => matches Ok(()))]
+fn tree_agrees_with_interpreter(filename: &str, bounds: &[(i64, i64)]) -> Result<()> {
+    let (workflows, parts) = parse_input(filename)?;
+    let tree = DecisionTree::new(&workflows);
+    for part in &parts {
+        if tree.accepts(part) != workflows.run(part) {
+            anyhow::bail!("Tree and interpreter disagree on {:?}", part);
+        }
+    }
+    let mut expected = 0u64;
+    let mut part: Part = bounds.iter().map(|&(min, _)| min).collect();
+    'outer: loop {
+        let accepted = workflows.run(&part);
+        if tree.accepts(&part) != accepted {
+            anyhow::bail!("Tree and interpreter disagree on {:?}", part);
+        }
+        expected += accepted as u64;
+        for (v, &(min, max)) in part.iter_mut().zip(bounds) {
+            if *v < max {
+                *v += 1;
+                continue 'outer;
             }
-            Rule::Al(dst) => {
-                ret += traverse_rules(workflows, *dst, inputs);
+            *v = min;
+        }
+        break;
+    }
+    let count = tree.count(bounds);
+    if count != expected.into() {
+        anyhow::bail!(
+            "Tree counts {} accepted parts instead of {}",
+            count,
+            expected
+        );
+    }
+    let boxes = tree.boxes(bounds);
+    for (i, a) in boxes.iter().enumerate() {
+        for b in &boxes[i + 1..] {
+            if a.iter().zip(b).all(|(p, q)| p.1 >= q.0 && q.1 >= p.0) {
+                anyhow::bail!("Boxes {:?} and {:?} overlap", a, b);
             }
         }
     }
-    ret
+    Ok(())
 }
 
 #[test_case("inputs/example-19-1.txt" => matches Ok(167409079868000))]
+#[test_case("inputs/example-19-2.txt" => matches Ok(167409079868000))]
+#[test_case("inputs/example-19-9.txt" => matches Ok(256000000000000))]
 #[test_case("inputs/input-19.txt" => matches Ok(131899818301477))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
     let (workflows, _) = parse_input(filename)?;
-    let ret = traverse_rules(&workflows, parse_id("in"), [(1, 4000); 4]);
-    Ok(ret)
+    let bounds = vec![(1, 4000); workflows.categories().len()];
+    Ok(i64::try_from(DecisionTree::new(&workflows).count(&bounds))?)
 }
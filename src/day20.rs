@@ -1,156 +1,333 @@
 use anyhow::Result;
-use smallbitset::Set64;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use test_case::test_case;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
-enum Module {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Module {
+    Button,
     Broadcast,
     FlipFlop,
     Conjunction,
+    Sink,
 }
 
-fn parse_input(filename: &str) -> Result<Vec<(Module, Set64)>> {
-    let input = std::fs::read_to_string(filename)?;
-    let mut input: Vec<(String, Module, Vec<String>)> = input
-        .lines()
-        .map(|l| {
-            let (src, dst) = l.split_once(" -> ").unwrap();
-            let (src, module) = if src == "broadcaster" {
-                ("".to_owned(), Module::Broadcast)
-            } else if let Some(src) = src.strip_prefix('%') {
-                (src.to_owned(), Module::FlipFlop)
-            } else if let Some(src) = src.strip_prefix('&') {
-                (src.to_owned(), Module::Conjunction)
-            } else {
-                unreachable!()
-            };
-            let dst: Vec<String> = dst.split(", ").map(|s| s.to_owned()).collect();
-            (src, module, dst)
-        })
-        .collect();
-    input.sort();
-    let mut extra = input
-        .iter()
-        .flat_map(|(_, _, dst)| dst)
-        .filter(|&s| !input.iter().any(|(src, _, _)| src == s))
-        .cloned()
-        .collect::<Vec<_>>();
-    extra.sort();
-    extra.dedup();
-    for s in extra {
-        input.push((s, Module::Broadcast, Vec::new()));
-    }
-    let ret = input
-        .iter()
-        .map(|(_, module, dst)| {
-            let mut set = Set64::empty();
-            for d in dst.iter() {
-                set.add_inplace(input.iter().position(|(s, _, _)| s == d).unwrap());
-            }
-            (*module, set)
-        })
-        .collect();
-    Ok(ret)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Stateless,
+    FlipFlop(bool),
+    Conjunction(Vec<bool>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pulse {
+    pub press: usize,
+    pub sender: usize,
+    pub receiver: usize,
+    pub high: bool,
 }
 
-fn build_refs(input: &[(Module, Set64)]) -> [Set64; 64] {
-    let mut refs = [Set64::empty(); 64];
-    for (i, (_, dst)) in input.iter().enumerate() {
-        for dst in dst.ones() {
-            refs[dst].add_inplace(i);
+#[derive(Debug, Default)]
+pub struct Circuit {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    kinds: Vec<Module>,
+    outputs: Vec<Vec<(usize, usize)>>,
+    inputs: Vec<Vec<usize>>,
+}
+
+impl Circuit {
+    fn intern(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.index.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.index.insert(name.to_string(), id);
+        self.kinds.push(Module::Sink);
+        self.outputs.push(Vec::new());
+        self.inputs.push(Vec::new());
+        id
+    }
+
+    fn connect(&mut self, src: usize, dst: usize) {
+        let slot = self.inputs[dst].len();
+        self.inputs[dst].push(src);
+        self.outputs[src].push((dst, slot));
+    }
+
+    pub fn module(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn kind(&self, id: usize) -> Module {
+        self.kinds[id]
+    }
+
+    pub fn inputs(&self, id: usize) -> &[usize] {
+        &self.inputs[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    fn initial_state(&self, id: usize) -> State {
+        match self.kinds[id] {
+            Module::FlipFlop => State::FlipFlop(false),
+            Module::Conjunction => State::Conjunction(vec![false; self.inputs[id].len()]),
+            _ => State::Stateless,
         }
     }
-    refs
 }
 
-fn press_button(
-    input: &[(Module, Set64)],
-    refs: &[Set64; 64],
-    flipflops: &mut Set64,
-    conjunctions: &mut [Set64; 64],
-    watch_conj: usize,
-) -> ([usize; 2], Set64) {
-    let mut watch = Set64::empty();
-    let mut pulses = VecDeque::new();
-    let mut counts = [0, 0];
-    pulses.push_back((0, Set64::singleton(0), false));
-    while let Some((src, ids, pulse)) = pulses.pop_front() {
-        for id in ids.ones() {
-            counts[pulse as usize] += 1;
-            match (input[id], pulse) {
-                ((Module::Broadcast, dst), pulse) => {
-                    if dst != Set64::empty() {
-                        pulses.push_back((id, dst, pulse));
-                    }
+fn parse_input(filename: &str) -> Result<Circuit> {
+    let input = std::fs::read_to_string(filename)?;
+    let mut circuit = Circuit::default();
+    let button = circuit.intern("button");
+    circuit.kinds[button] = Module::Button;
+    let mut links = Vec::new();
+    for l in input.lines().filter(|l| !l.is_empty()) {
+        let (src, dst) = l
+            .split_once(" -> ")
+            .ok_or_else(|| anyhow::anyhow!("Invalid line {:?}", l))?;
+        let (src, module) = if src == "broadcaster" {
+            (src, Module::Broadcast)
+        } else if let Some(src) = src.strip_prefix('%') {
+            (src, Module::FlipFlop)
+        } else if let Some(src) = src.strip_prefix('&') {
+            (src, Module::Conjunction)
+        } else {
+            anyhow::bail!("Unknown module type in {:?}", l);
+        };
+        let id = circuit.intern(src);
+        circuit.kinds[id] = module;
+        links.push((id, dst));
+    }
+    let broadcaster = circuit
+        .module("broadcaster")
+        .ok_or_else(|| anyhow::anyhow!("No broadcaster"))?;
+    circuit.connect(button, broadcaster);
+    for (id, dst) in links {
+        for d in dst.split(", ") {
+            let d = circuit.intern(d);
+            circuit.connect(id, d);
+        }
+    }
+    Ok(circuit)
+}
+
+pub struct Simulator<'a> {
+    circuit: &'a Circuit,
+    states: Vec<State>,
+    presses: usize,
+    counts: [usize; 2],
+    trace: Option<Vec<Pulse>>,
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(circuit: &'a Circuit) -> Self {
+        Simulator {
+            circuit,
+            states: (0..circuit.len())
+                .map(|id| circuit.initial_state(id))
+                .collect(),
+            presses: 0,
+            counts: [0, 0],
+            trace: None,
+        }
+    }
+
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Vec::new());
+        self
+    }
+
+    pub fn state(&self, id: usize) -> &State {
+        &self.states[id]
+    }
+
+    pub fn presses(&self) -> usize {
+        self.presses
+    }
+
+    pub fn counts(&self) -> [usize; 2] {
+        self.counts
+    }
+
+    pub fn press(&mut self) {
+        self.press_with(|_| ());
+    }
+
+    pub fn press_with(&mut self, mut observe: impl FnMut(&Pulse)) {
+        self.presses += 1;
+        let button = self.circuit.module("button").unwrap();
+        let mut pulses = VecDeque::new();
+        for &(dst, slot) in &self.circuit.outputs[button] {
+            pulses.push_back((button, dst, slot, false));
+        }
+        while let Some((src, id, slot, high)) = pulses.pop_front() {
+            let pulse = Pulse {
+                press: self.presses,
+                sender: src,
+                receiver: id,
+                high,
+            };
+            self.counts[high as usize] += 1;
+            observe(&pulse);
+            if let Some(trace) = &mut self.trace {
+                trace.push(pulse);
+            }
+            let out = match &mut self.states[id] {
+                State::FlipFlop(_) if high => None,
+                State::FlipFlop(on) => {
+                    *on = !*on;
+                    Some(*on)
                 }
-                ((Module::FlipFlop, dst), false) => {
-                    if flipflops.contains(id) {
-                        flipflops.remove_inplace(id);
-                    } else {
-                        flipflops.add_inplace(id);
-                    }
-                    pulses.push_back((id, dst, flipflops.contains(id)));
+                State::Conjunction(memory) => {
+                    memory[slot] = high;
+                    Some(!memory.iter().all(|&m| m))
                 }
-                ((Module::FlipFlop, _), true) => (),
-                ((Module::Conjunction, dst), pulse) => {
-                    if pulse {
-                        if id == watch_conj {
-                            watch.add_inplace(src);
-                        }
-                        conjunctions[id].add_inplace(src);
-                    } else {
-                        conjunctions[id].remove_inplace(src);
-                    }
-                    let pulse = !conjunctions[id].contains_all(refs[id]);
-                    pulses.push_back((id, dst, pulse));
+                State::Stateless => match self.circuit.kinds[id] {
+                    Module::Broadcast => Some(high),
+                    _ => None,
+                },
+            };
+            if let Some(out) = out {
+                for &(dst, slot) in &self.circuit.outputs[id] {
+                    pulses.push_back((id, dst, slot, out));
                 }
             }
         }
     }
-    (counts, watch)
+
+    pub fn trace(&self) -> &[Pulse] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    pub fn trace_for(&self, module: usize) -> impl Iterator<Item = &Pulse> {
+        self.trace()
+            .iter()
+            .filter(move |p| p.sender == module || p.receiver == module)
+    }
+
+    pub fn trace_csv(&self, module: Option<usize>) -> String {
+        let mut ret = String::from("press,sender,receiver,level\n");
+        for p in self.trace() {
+            if module.is_some_and(|m| p.sender != m && p.receiver != m) {
+                continue;
+            }
+            ret += &format!(
+                "{},{},{},{}\n",
+                p.press,
+                self.circuit.name(p.sender),
+                self.circuit.name(p.receiver),
+                if p.high { "high" } else { "low" }
+            );
+        }
+        ret
+    }
+}
+
+#[test_case("inputs/example-20-1.txt", 1, None => matches Ok(s) if s.lines().count() == 13)]
+#[test_case("inputs/example-20-1.txt", 1, Some("inv")
+    => matches Ok(s) if s == "press,sender,receiver,level\n1,c,inv,high\n1,inv,a,low\n1,c,inv,low\n1,inv,a,high\n")]
+#[test_case("inputs/example-20-2.txt", 2, Some("output")
+    => matches Ok(s) if s == "press,sender,receiver,level\n1,con,output,high\n1,con,output,low\n2,con,output,high\n")]
+#[test_case("inputs/example-20-2.txt", 1, Some("nope") => matches Err(_))]
+pub fn trace_csv(filename: &str, presses: usize, module: Option<&str>) -> Result<String> {
+    let circuit = parse_input(filename)?;
+    let module = module
+        .map(|m| {
+            circuit
+                .module(m)
+                .ok_or_else(|| anyhow::anyhow!("No module {}", m))
+        })
+        .transpose()?;
+    let mut sim = Simulator::new(&circuit).with_trace();
+    for _ in 0..presses {
+        sim.press();
+    }
+    Ok(sim.trace_csv(module))
+}
+
+pub fn write_trace(
+    filename: &str,
+    presses: usize,
+    module: Option<&str>,
+    output: &str,
+) -> Result<()> {
+    std::fs::write(output, trace_csv(filename, presses, module)?)?;
+    Ok(())
+}
+
+#[test_case("inputs/example-20-1.txt", 1 => matches Ok([8, 4]))]
+#[test_case("inputs/example-20-2.txt", 1000 => matches Ok([4250, 2750]))]
+#[test_case("inputs/example-20-3.txt", 5 => matches Ok([21, 23]))]
+pub fn pulse_counts(filename: &str, presses: usize) -> Result<[usize; 2]> {
+    let circuit = parse_input(filename)?;
+    let mut sim = Simulator::new(&circuit);
+    for _ in 0..presses {
+        sim.press();
+    }
+    Ok(sim.counts())
+}
+
+#[test_case("inputs/example-20-3.txt", 5, "f0" => matches Ok(State::FlipFlop(true)))]
+#[test_case("inputs/example-20-3.txt", 5, "f1" => matches Ok(State::FlipFlop(false)))]
+#[test_case("inputs/example-20-3.txt", 5, "f2" => matches Ok(State::FlipFlop(true)))]
+#[test_case("inputs/example-20-3.txt", 5, "f69" => matches Ok(State::FlipFlop(false)))]
+#[test_case("inputs/example-20-3.txt", 5, "all" => matches Ok(State::Conjunction(m)) if m.len() == 71 && m.iter().filter(|&&h| h).count() == 2)]
+#[test_case("inputs/example-20-3.txt", 5, "out" => matches Ok(State::Stateless))]
+pub fn module_state(filename: &str, presses: usize, module: &str) -> Result<State> {
+    let circuit = parse_input(filename)?;
+    let id = circuit
+        .module(module)
+        .ok_or_else(|| anyhow::anyhow!("No module {}", module))?;
+    let mut sim = Simulator::new(&circuit);
+    for _ in 0..presses {
+        sim.press();
+    }
+    Ok(sim.state(id).clone())
 }
 
 #[test_case("inputs/example-20-1.txt" => matches Ok(32000000))]
 #[test_case("inputs/example-20-2.txt" => matches Ok(11687500))]
 #[test_case("inputs/input-20.txt" => matches Ok(839775244))]
 pub fn puzzle1(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let refs = build_refs(&input);
-    let mut flipflops = Set64::empty();
-    let mut conjunctions = [Set64::empty(); 64];
-    let mut counts = [0; 2];
-    for _ in 0..1000 {
-        let (c, _) = press_button(&input, &refs, &mut flipflops, &mut conjunctions, 0);
-        counts[0] += c[0];
-        counts[1] += c[1];
-    }
-    let ret = counts[0] as i64 * counts[1] as i64;
-    Ok(ret)
+    let [low, high] = pulse_counts(filename, 1000)?;
+    Ok(low as i64 * high as i64)
 }
 
 #[test_case("inputs/input-20.txt" => matches Ok(207787533680413))]
 pub fn puzzle2(filename: &str) -> Result<i64> {
-    let input = parse_input(filename)?;
-    let refs = build_refs(&input);
-    let mut flipflops = Set64::empty();
-    let mut conjunctions = [Set64::empty(); 64];
-    let last_conj = refs[input.len() - 1].iter().next().unwrap();
-    let last_refs = refs[last_conj].iter().collect::<Vec<_>>();
-    let mut last_high = [None; 64];
-    for counter in 1.. {
-        let (_, watch) = press_button(&input, &refs, &mut flipflops, &mut conjunctions, last_conj);
-        for i in watch.iter() {
-            last_high[i] = Some(counter);
-        }
-        if last_refs.iter().all(|i| last_high[*i].is_some()) {
-            break;
-        }
+    let circuit = parse_input(filename)?;
+    let rx = circuit
+        .module("rx")
+        .ok_or_else(|| anyhow::anyhow!("No rx module"))?;
+    let last_conj = circuit.inputs(rx)[0];
+    let last_refs = circuit.inputs(last_conj);
+    let mut last_high = vec![None; circuit.len()];
+    let mut sim = Simulator::new(&circuit);
+    while last_refs.iter().any(|&i| last_high[i].is_none()) {
+        let press = sim.presses() as i64 + 1;
+        sim.press_with(|p| {
+            if p.high && p.receiver == last_conj {
+                last_high[p.sender] = Some(press);
+            }
+        });
     }
     // XXX: should be LCD, but humbug
     let ret = last_refs
         .iter()
-        .map(|i| last_high[*i].unwrap())
+        .map(|&i| last_high[i].unwrap())
         .product::<i64>();
     Ok(ret)
 }
@@ -48,7 +48,7 @@ fn intersects(a: Block, b: Block) -> bool {
     x1 <= x4 && x2 >= x3 && y1 <= y4 && y2 >= y3 && z1 <= z4 && z2 >= z3
 }
 
-fn fall_blocks(input: &mut Vec<(Point, Point)>) {
+fn fall_blocks(input: &mut [(Point, Point)]) {
     for i in 0..input.len() {
         let mut cur = input[i];
         while let Some(fall_block) = one_down(cur) {
@@ -0,0 +1,70 @@
+use test_case::test_case;
+
+const UNSEEN: usize = usize::MAX;
+
+fn build_path(parent: &[usize], goal: usize) -> Vec<usize> {
+    let mut path = vec![goal];
+    let mut node = goal;
+    while parent[node] != node {
+        node = parent[node];
+        path.push(node);
+    }
+    path.reverse();
+    path
+}
+
+#[test_case(10, 3, 0, |n| [(n + 1, 2), (n + 2, 3)].into_iter().filter(|&(m, _)| m < 10), |n| n == 9 => matches Some((_, 14)))]
+#[test_case(10, 3, 0, |n| [(n + 1, 2), (n + 2, 3)].into_iter().filter(|&(m, _)| m < 10), |n| n == 0 => matches Some((ref p, 0)) if p == &[0])]
+#[test_case(10, 1, 0, |n| [(n + 1, 0), (n + 2, 1)].into_iter().filter(|&(m, _)| m < 10), |n| n == 9 => matches Some((ref p, 0)) if p.len() == 10)]
+#[test_case(10, 5, 0, |n| [(n + 2, 5)].into_iter().filter(|&(m, _)| m < 10), |n| n == 9 => None)]
+#[test_case(10, 3, 0, |n| [(n + 7, 3)], |n| n == 9 => panics "successor 14 outside 10 states")]
+pub fn dial<FN, IN, FS>(
+    states: usize,
+    max_weight: usize,
+    start: usize,
+    mut successors: FN,
+    mut success: FS,
+) -> Option<(Vec<usize>, usize)>
+where
+    FN: FnMut(usize) -> IN,
+    IN: IntoIterator<Item = (usize, usize)>,
+    FS: FnMut(usize) -> bool,
+{
+    let mut dist = vec![UNSEEN; states];
+    let mut parent = vec![UNSEEN; states];
+    let width = max_weight + 1;
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); width];
+    let mut queued = 1;
+    dist[start] = 0;
+    parent[start] = start;
+    buckets[0].push(start);
+    let mut cost = 0;
+    while queued > 0 {
+        let slot = cost % width;
+        while let Some(node) = buckets[slot].pop() {
+            queued -= 1;
+            if dist[node] != cost {
+                continue;
+            }
+            if success(node) {
+                return Some((build_path(&parent, node), cost));
+            }
+            for (next, weight) in successors(node) {
+                assert!(next < states, "successor {next} outside {states} states");
+                assert!(
+                    weight <= max_weight,
+                    "edge weight {weight} above {max_weight}"
+                );
+                let c = cost + weight;
+                if c < dist[next] {
+                    dist[next] = c;
+                    parent[next] = node;
+                    buckets[c % width].push(next);
+                    queued += 1;
+                }
+            }
+        }
+        cost += 1;
+    }
+    None
+}
@@ -0,0 +1,126 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dig {
+    pub dir: (isize, isize),
+    pub count: usize,
+    pub color: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    Empty,
+    EmptyStep(usize),
+    Unclosed((i64, i64)),
+    SelfIntersecting(usize, usize),
+}
+
+impl std::fmt::Display for PlanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanError::Empty => write!(f, "Dig plan has no instructions"),
+            PlanError::EmptyStep(i) => write!(f, "Instruction {} digs nothing", i),
+            PlanError::Unclosed(pos) => {
+                write!(f, "Dig plan ends at {:?} instead of the start", pos)
+            }
+            PlanError::SelfIntersecting(i, j) => {
+                write!(f, "Instructions {} and {} cross the trench", i, j)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+type Point = (i64, i64);
+
+#[derive(Debug, Clone)]
+pub struct Lagoon {
+    vertices: Vec<Point>,
+    plan: Vec<Dig>,
+}
+
+fn span(a: Point, b: Point) -> (Point, Point) {
+    ((a.0.min(b.0), a.1.min(b.1)), (a.0.max(b.0), a.1.max(b.1)))
+}
+
+fn touches(a: (Point, Point), b: (Point, Point)) -> bool {
+    a.0 .0 <= b.1 .0 && b.0 .0 <= a.1 .0 && a.0 .1 <= b.1 .1 && b.0 .1 <= a.1 .1
+}
+
+impl Lagoon {
+    pub fn new(plan: &[Dig]) -> Result<Self, PlanError> {
+        if plan.is_empty() {
+            return Err(PlanError::Empty);
+        }
+        let mut pos = (0, 0);
+        let mut vertices = vec![pos];
+        for (i, dig) in plan.iter().enumerate() {
+            if dig.count == 0 {
+                return Err(PlanError::EmptyStep(i));
+            }
+            pos.0 += dig.dir.0 as i64 * dig.count as i64;
+            pos.1 += dig.dir.1 as i64 * dig.count as i64;
+            vertices.push(pos);
+        }
+        if pos != (0, 0) {
+            return Err(PlanError::Unclosed(pos));
+        }
+        let n = plan.len();
+        let edges: Vec<_> = vertices.windows(2).map(|w| span(w[0], w[1])).collect();
+        for i in 0..n {
+            let next = (i + 1) % n;
+            if plan[next].dir == (-plan[i].dir.0, -plan[i].dir.1) {
+                return Err(PlanError::SelfIntersecting(i.min(next), i.max(next)));
+            }
+            for j in i + 2..n {
+                if (j + 1) % n != i && touches(edges[i], edges[j]) {
+                    return Err(PlanError::SelfIntersecting(i, j));
+                }
+            }
+        }
+        Ok(Lagoon {
+            vertices,
+            plan: plan.to_vec(),
+        })
+    }
+
+    pub fn boundary(&self) -> i64 {
+        self.plan.iter().map(|dig| dig.count as i64).sum()
+    }
+
+    fn twice_area(&self) -> i64 {
+        self.vertices
+            .windows(2)
+            .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+            .sum::<i64>()
+            .abs()
+    }
+
+    pub fn interior(&self) -> i64 {
+        (self.twice_area() - self.boundary() + 2) / 2
+    }
+
+    pub fn total(&self) -> i64 {
+        self.interior() + self.boundary()
+    }
+
+    pub fn to_svg(&self) -> String {
+        let (min, max) = self.vertices.iter().fold(((0, 0), (0, 0)), |(lo, hi), &p| {
+            (span(lo, p).0, span(hi, p).1)
+        });
+        let mut ret = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min.1 - 1,
+            min.0 - 1,
+            max.1 - min.1 + 2,
+            max.0 - min.0 + 2
+        );
+        for (w, dig) in self.vertices.windows(2).zip(&self.plan) {
+            ret += &format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#{:06x}\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\"/>\n",
+                w[0].1, w[0].0, w[1].1, w[1].0, dig.color
+            );
+        }
+        ret += "</svg>\n";
+        ret
+    }
+}
@@ -1,3 +1,4 @@
+pub mod cycle;
 pub mod day01;
 pub mod day02;
 pub mod day03;
@@ -23,3 +24,6 @@ pub mod day22;
 pub mod day23;
 pub mod day24;
 pub mod day25;
+pub mod dial;
+pub mod lagoon;
+pub mod nonogram;
@@ -0,0 +1,124 @@
+use crate::day12::{Spring, SpringRow};
+use anyhow::Result;
+use num::Zero;
+use test_case::test_case;
+
+pub type Grid = Vec<Vec<Spring>>;
+pub type Clues = Vec<Vec<i64>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Solution {
+    Unique(Grid),
+    Multiple(Grid, Grid),
+    None,
+}
+
+fn parse_clue(line: &str) -> Result<Vec<i64>> {
+    let groups = line
+        .split(',')
+        .map(|v| v.trim().parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(groups.into_iter().filter(|&g| g > 0).collect())
+}
+
+pub fn parse_clues(filename: &str) -> Result<(Clues, Clues)> {
+    let input = std::fs::read_to_string(filename)?;
+    let Some((rows, cols)) = input.split_once("\n\n") else {
+        return Err(anyhow::anyhow!(
+            "Missing blank line between row and column clues"
+        ));
+    };
+    let rows = rows.lines().map(parse_clue).collect::<Result<Vec<_>>>()?;
+    let cols = cols.lines().map(parse_clue).collect::<Result<Vec<_>>>()?;
+    Ok((rows, cols))
+}
+
+fn solve_line(line: &mut [Spring], clue: &[i64]) -> Option<bool> {
+    let row = SpringRow::new(line, clue);
+    let total = row.count();
+    if total.is_zero() {
+        return None;
+    }
+    let mut changed = false;
+    for (cell, d) in line.iter_mut().zip(row.damage_counts()) {
+        let known = if d.is_zero() {
+            Spring::Ope
+        } else if d == total {
+            Spring::Dam
+        } else {
+            continue;
+        };
+        if *cell != known {
+            *cell = known;
+            changed = true;
+        }
+    }
+    Some(changed)
+}
+
+fn propagate(grid: &mut Grid, rows: &[Vec<i64>], cols: &[Vec<i64>]) -> Option<()> {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (line, clue) in grid.iter_mut().zip(rows) {
+            changed |= solve_line(line, clue)?;
+        }
+        for (c, clue) in cols.iter().enumerate() {
+            let mut line: Vec<Spring> = grid.iter().map(|r| r[c]).collect();
+            if solve_line(&mut line, clue)? {
+                changed = true;
+                for (r, cell) in line.into_iter().enumerate() {
+                    grid[r][c] = cell;
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+fn search(mut grid: Grid, rows: &[Vec<i64>], cols: &[Vec<i64>], found: &mut Vec<Grid>) {
+    if found.len() >= 2 || propagate(&mut grid, rows, cols).is_none() {
+        return;
+    }
+    let unknown = grid.iter().enumerate().find_map(|(r, line)| {
+        line.iter()
+            .position(|&cell| cell == Spring::Unk)
+            .map(|c| (r, c))
+    });
+    let Some((r, c)) = unknown else {
+        found.push(grid);
+        return;
+    };
+    for guess in [Spring::Dam, Spring::Ope] {
+        let mut next = grid.clone();
+        next[r][c] = guess;
+        search(next, rows, cols, found);
+    }
+}
+
+pub fn solve(rows: &[Vec<i64>], cols: &[Vec<i64>]) -> Solution {
+    let grid = vec![vec![Spring::Unk; cols.len()]; rows.len()];
+    let mut found = Vec::new();
+    search(grid, rows, cols, &mut found);
+    let mut found = found.into_iter();
+    match (found.next(), found.next()) {
+        (Some(a), None) => Solution::Unique(a),
+        (Some(a), Some(b)) => Solution::Multiple(a, b),
+        _ => Solution::None,
+    }
+}
+
+pub fn render(grid: &Grid) -> String {
+    grid.iter()
+        .map(|line| crate::day12::springs_to_string(line) + "\n")
+        .collect()
+}
+
+#[test_case("inputs/nonogram-1.txt" => matches Ok(Solution::Unique(g)) if render(&g) == ".###.\n#...#\n#####\n#...#\n#...#\n")]
+#[test_case("inputs/nonogram-2.txt" => matches Ok(Solution::Multiple(_, _)))]
+#[test_case("inputs/nonogram-3.txt" => matches Ok(Solution::None))]
+#[test_case("inputs/nonogram-4.txt" => matches Ok(Solution::Unique(g)) if render(&g) == "#..".repeat(60) + &".".repeat(20) + "\n")]
+pub fn solve_file(filename: &str) -> Result<Solution> {
+    let (rows, cols) = parse_clues(filename)?;
+    Ok(solve(&rows, &cols))
+}